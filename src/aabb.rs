@@ -0,0 +1,152 @@
+use crate::ray::Ray;
+use crate::vec::Vec3f;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    min: Vec3f,
+    max: Vec3f,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3f, max: Vec3f) -> Aabb {
+        Aabb { min, max }
+    }
+
+    // A box that contains nothing, useful as the starting point of a union
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vec3f::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vec3f::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn min(&self) -> Vec3f {
+        self.min
+    }
+
+    pub fn max(&self) -> Vec3f {
+        self.max
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3f::new(
+                self.min.x().min(other.min.x()),
+                self.min.y().min(other.min.y()),
+                self.min.z().min(other.min.z()),
+            ),
+            max: Vec3f::new(
+                self.max.x().max(other.max.x()),
+                self.max.y().max(other.max.y()),
+                self.max.z().max(other.max.z()),
+            ),
+        }
+    }
+
+    pub fn grow(&self, p: Vec3f) -> Aabb {
+        self.union(&Aabb::new(p, p))
+    }
+
+    pub fn centroid(&self) -> Vec3f {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vec3f {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.extent();
+        if d.x() < 0.0 || d.y() < 0.0 || d.z() < 0.0 {
+            return 0.0;
+        }
+
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    // Index of the longest axis: 0 for x, 1 for y and 2 for z
+    pub fn largest_axis(&self) -> usize {
+        let d = self.extent();
+        if d.x() > d.y() && d.x() > d.z() {
+            0
+        } else if d.y() > d.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Slab test. Takes the reciprocal of the ray direction so it can be
+    // computed once per ray instead of once per box.
+    pub fn hit(&self, ray: &Ray, inv_dir: Vec3f, t_min: f64, t_max: f64) -> bool {
+        let orig = ray.origin();
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+
+        for a in 0..3 {
+            let t0 = (self.min.get(a) - orig.get(a)) * inv_dir.get(a);
+            let t1 = (self.max.get(a) - orig.get(a)) * inv_dir.get(a);
+            let (t0, t1) = if inv_dir.get(a) < 0.0 {
+                (t1, t0)
+            } else {
+                (t0, t1)
+            };
+
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::aabb::Aabb;
+    use crate::ray::Ray;
+    use crate::vec::Vec3f;
+
+    fn inv(r: &Ray) -> Vec3f {
+        Vec3f::new(
+            1.0 / r.direction().x(),
+            1.0 / r.direction().y(),
+            1.0 / r.direction().z(),
+        )
+    }
+
+    #[test]
+    fn aabb_union_and_area() {
+        let a = Aabb::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(1.0, 1.0, 1.0));
+        let b = Aabb::new(Vec3f::new(-2.0, 0.5, 0.0), Vec3f::new(0.5, 2.0, 1.0));
+        let u = a.union(&b);
+        assert_eq!(u.min(), Vec3f::new(-2.0, 0.0, 0.0));
+        assert_eq!(u.max(), Vec3f::new(1.0, 2.0, 1.0));
+        assert_eq!(a.surface_area(), 6.0);
+        assert_eq!(Aabb::empty().surface_area(), 0.0);
+        assert_eq!(Aabb::empty().union(&a), a);
+        assert_eq!(u.largest_axis(), 0);
+    }
+
+    #[test]
+    fn aabb_hit_and_miss() {
+        let b = Aabb::new(Vec3f::new(-1.0, -1.0, -1.0), Vec3f::new(1.0, 1.0, 1.0));
+
+        let towards = Ray::new(Vec3f::new(0.0, 0.0, -5.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(b.hit(&towards, inv(&towards), 0.0, f64::MAX));
+        // The box starts past t_max
+        assert!(!b.hit(&towards, inv(&towards), 0.0, 3.0));
+
+        let away = Ray::new(Vec3f::new(0.0, 0.0, -5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!b.hit(&away, inv(&away), 0.0, f64::MAX));
+
+        let beside = Ray::new(Vec3f::new(2.0, 0.0, -5.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(!b.hit(&beside, inv(&beside), 0.0, f64::MAX));
+
+        let diagonal = Ray::new(Vec3f::new(-5.0, -5.0, -5.0), Vec3f::new(1.0, 1.0, 1.0));
+        assert!(b.hit(&diagonal, inv(&diagonal), 0.0, f64::MAX));
+    }
+}
@@ -1,72 +1,632 @@
 extern crate minifb;
-extern crate png;
 extern crate shade_tree;
 
-use minifb::{Key, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use shade_tree::{
+    AdaptiveSampling, Framebuffer, RenderJob, RenderSettings, Tile, TileOrder, ToneMap,
+    ToneMapping, Vec3f,
+};
 use std::env;
-use std::fs::File;
-use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
 
-const WIDTH: usize = 1280;
-const HEIGHT: usize = 720;
-const DEFAULT_SAMPLES: usize = 30; // Increase for better anti-aliasing
+const USAGE: &str = "\
+Usage: shady [OPTIONS] [SCENE.toml | SAMPLES] [OUTPUT]
 
-fn window_run(samples: usize) {
-    let buffer = shade_tree::render(WIDTH, HEIGHT, samples);
+Renders a scene file, or the random spheres scene if none is given, to the
+OUTPUT image or to a window.
+
+Options:
+  -s, --scene FILE          Scene file to render
+  -o, --output FILE         Image to write instead of opening a window
+  -f, --format FORMAT       Image format, instead of the output's extension:
+                            png, ppm, pfm, exr or hdr
+  -W, --width PIXELS        Image width
+  -H, --height PIXELS       Image height
+  -n, --spp SAMPLES         Samples per pixel, the most any pixel gets with
+                            --adaptive
+  -a, --adaptive ERROR      Stop sampling pixels once their relative noise is
+                            below ERROR, like 0.01
+      --min-samples SAMPLES Samples every pixel gets with --adaptive
+      --heatmap FILE        Also write an image of the samples each pixel got
+  -d, --max-depth BOUNCES   Bounces after which paths are cut short
+      --seed SEED           Seed for the noise pattern and the random scene
+  -j, --threads COUNT       Render threads, one per core by default
+  -i, --integrator NAME     path, mis, normals, depth, albedo, hit_count or ao
+      --sampler NAME        independent, stratified, halton or sobol
+  -t, --tone-map NAME       clamp, reinhard or aces
+  -e, --exposure STOPS      Brighten or darken the image
+      --tile-size PIXELS    Side of the square tiles the image is split into
+      --tile-order NAME     scanline, spiral or hilbert
+  -h, --help                Print this message
+
+Options override the scene's [render] section. Values can also be given as
+--name=VALUE.
+";
+
+// What the render thread tells the window about
+enum Update {
+    // A tile finished its pass, as 0xRRGGBB pixels
+    Tile(Tile, Vec<u32>),
+    // Every tile has this many samples now, and the image so far for saving
+    Pass(usize, Framebuffer),
+}
+
+// Opens the window right away and keeps refining the image on another thread,
+// one pass over the whole frame at a time, until every sample is in or the
+// window gets closed. Tiles show up as soon as they're done. The render
+// thread owns the job, so the window never waits for it except to stop it,
+// which happens as soon as the tiles in progress are done.
+fn window_run(job: RenderJob) -> Result<(), String> {
+    let (width, height) = (job.settings().width, job.settings().height);
+    let samples = job.settings().samples;
+    let tone_mapping = job.settings().tone_mapping;
 
     let mut window = Window::new(
-        "Test - ESC to exit",
-        WIDTH,
-        HEIGHT,
+        "Shade Tree - ESC to exit",
+        width,
+        height,
         WindowOptions::default(),
     )
-    .unwrap_or_else(|e| {
-        panic!("Error: {}", e);
-    });
+    .map_err(|e| format!("could not open a window: {}", e))?;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+    let worker = {
+        let stop = Arc::clone(&stop);
+        let mut job = job;
+        thread::spawn(move || {
+            while !job.is_done() {
+                let finished = job.render_pass_until(&stop, |tile, pixels| {
+                    let hex = pixels.iter().map(|c| tone_mapping.to_hex(*c)).collect();
+                    // The window going away sets `stop`
+                    let _ = sender.send(Update::Tile(*tile, hex));
+                });
+                if !finished {
+                    break;
+                }
+
+                let update = Update::Pass(job.samples_done(), job.framebuffer());
+                if sender.send(update).is_err() {
+                    break;
+                }
+            }
+        })
+    };
 
+    let mut buffer: Vec<u32> = vec![0; width * height];
+    // The last whole pass, which is what S saves
+    let mut image = Framebuffer::new(width, height, vec![Vec3f::default(); width * height])
+        .ok_or_else(|| format!("can't show a {}x{} image", width, height))?;
+    let mut done = 0;
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        window.update_with_buffer(&buffer).unwrap();
+        for update in receiver.try_iter() {
+            match update {
+                Update::Tile(tile, pixels) => {
+                    for (row, line) in pixels.chunks(tile.width).enumerate() {
+                        let start = (tile.y + row) * width + tile.x;
+                        buffer[start..start + tile.width].copy_from_slice(line);
+                    }
+                }
+                Update::Pass(passes, framebuffer) => {
+                    done = passes;
+                    image = framebuffer;
+                    window.set_title(&format!(
+                        "Shade Tree - {}/{} spp - S to save, ESC to exit",
+                        done, samples
+                    ));
+                }
+            }
+        }
+
+        // Saves the linear image too, for compositing
+        if window.is_key_pressed(Key::S, KeyRepeat::No) {
+            let name = format!("shade-tree-{}spp", done);
+            for extension in ["png", "exr"].iter() {
+                let filename = format!("{}.{}", name, extension);
+                if let Err(e) = output_file(&image, &tone_mapping, Path::new(&filename), None) {
+                    eprintln!("Error writing {}: {}", filename, e);
+                }
+            }
+        }
+
+        if let Err(e) = window.update_with_buffer(&buffer) {
+            stop.store(true, Ordering::Relaxed);
+            let _ = worker.join();
+            return Err(format!("could not update the window: {}", e));
+        }
+        thread::sleep(Duration::from_millis(16));
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    worker
+        .join()
+        .map_err(|_| "the render thread panicked".to_string())
+}
+
+// Writes the image next to where the program was run, in `format` or the one
+// matching the file extension
+fn output_file(
+    image: &Framebuffer,
+    tone_mapping: &ToneMapping,
+    filename: &Path,
+    format: Option<&str>,
+) -> shade_tree::Result<()> {
+    let path = env::current_dir()?.join(filename);
+    println!("Writing to file: {}", path.display());
+
+    match format {
+        Some(format) => shade_tree::save_image_as(&path, format, image, tone_mapping),
+        None => shade_tree::save_image(&path, image, tone_mapping),
     }
 }
 
-fn output_file(samples: usize, filename: String) {
-    let mut path = env::current_dir().unwrap();
-    path.push(filename);
-    println!("Rendering to file: {}", path.display());
+// Everything given on the command line, None for what wasn't
+#[derive(Debug, Default)]
+struct Options {
+    scene: Option<PathBuf>,
+    output: Option<PathBuf>,
+    format: Option<String>,
+    width: Option<usize>,
+    height: Option<usize>,
+    samples: Option<usize>,
+    max_depth: Option<usize>,
+    seed: Option<u64>,
+    threads: Option<usize>,
+    integrator: Option<String>,
+    sampler: Option<String>,
+    tone_map: Option<ToneMap>,
+    exposure: Option<f64>,
+    tile_size: Option<usize>,
+    tile_order: Option<TileOrder>,
+    adaptive: Option<f64>,
+    min_samples: Option<usize>,
+    heatmap: Option<PathBuf>,
+    help: bool,
+}
 
-    let buffer = shade_tree::render(WIDTH, HEIGHT, samples);
+fn set<T>(slot: &mut Option<T>, name: &str, value: T) -> Result<(), String> {
+    if slot.is_some() {
+        return Err(format!("{} given more than once", name));
+    }
+    *slot = Some(value);
 
-    let file = File::create(path).unwrap();
-    let w = &mut BufWriter::new(file);
+    Ok(())
+}
 
-    let mut encoder = png::Encoder::new(w, WIDTH as u32, HEIGHT as u32);
-    encoder.set_color(png::ColorType::RGB);
-    encoder.set_depth(png::BitDepth::Eight);
-    let mut writer = encoder.write_header().unwrap();
+fn number<T: FromStr>(name: &str, value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| format!("invalid value {} for {}: {}", value, name, e))
+}
 
-    let data = shade_tree::vec_from_hex(buffer);
-    writer.write_image_data(data.as_slice()).unwrap();
+// At least 1, for sizes and counts
+fn positive(name: &str, value: &str) -> Result<usize, String> {
+    match number(name, value)? {
+        0 => Err(format!("{} must be at least 1", name)),
+        n => Ok(n),
+    }
 }
 
-fn main() {
-    println!("Shade Tree");
+fn one_of(kind: &str, value: &str, names: &[&str]) -> Result<String, String> {
+    if names.contains(&value) {
+        Ok(value.to_string())
+    } else {
+        Err(format!(
+            "unknown {} {}, expected one of: {}",
+            kind,
+            value,
+            names.join(", ")
+        ))
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+// Parses the arguments after the program name. Everything is checked here,
+// so a typo doesn't show up after a long render.
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            positional.push(arg.clone());
+            continue;
+        }
+
+        // --name=VALUE or --name VALUE
+        let (name, inline) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => (&arg[..i], Some(arg[i + 1..].to_string())),
+            _ => (arg.as_str(), None),
+        };
+        if name == "-h" || name == "--help" {
+            options.help = true;
+            continue;
+        }
+        let mut value = || match inline.clone() {
+            Some(v) => Ok(v),
+            None => args
+                .next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", name)),
+        };
 
-    let args: Vec<String> = env::args().collect();
-    let samples = if args.len() > 1 {
-        match args[1].parse::<usize>() {
-            Ok(s) => s,
-            Err(e) => {
-                println!("Error parsing samples per pixel: {}", e);
-                DEFAULT_SAMPLES
+        match name {
+            "-s" | "--scene" => set(&mut options.scene, name, PathBuf::from(value()?))?,
+            "-o" | "--output" => set(&mut options.output, name, PathBuf::from(value()?))?,
+            "-f" | "--format" => {
+                let format = one_of("image format", &value()?, &shade_tree::IMAGE_FORMATS)?;
+                set(&mut options.format, name, format)?
+            }
+            "-W" | "--width" => set(&mut options.width, name, positive(name, &value()?)?)?,
+            "-H" | "--height" => set(&mut options.height, name, positive(name, &value()?)?)?,
+            "-n" | "--spp" | "--samples" => {
+                set(&mut options.samples, name, positive(name, &value()?)?)?
+            }
+            "-a" | "--adaptive" => {
+                let threshold: f64 = number(name, &value()?)?;
+                if !(threshold > 0.0 && threshold.is_finite()) {
+                    return Err(format!("{} must be a positive number", name));
+                }
+                set(&mut options.adaptive, name, threshold)?
+            }
+            "--min-samples" => set(&mut options.min_samples, name, positive(name, &value()?)?)?,
+            "--heatmap" => set(&mut options.heatmap, name, PathBuf::from(value()?))?,
+            "-d" | "--max-depth" => set(&mut options.max_depth, name, number(name, &value()?)?)?,
+            "--seed" => set(&mut options.seed, name, number(name, &value()?)?)?,
+            "-j" | "--threads" => set(&mut options.threads, name, positive(name, &value()?)?)?,
+            "-i" | "--integrator" => {
+                let integrator = one_of("integrator", &value()?, &shade_tree::INTEGRATOR_NAMES)?;
+                set(&mut options.integrator, name, integrator)?
+            }
+            "--sampler" => {
+                let sampler = one_of("sampler", &value()?, &shade_tree::SAMPLER_NAMES)?;
+                set(&mut options.sampler, name, sampler)?
             }
+            "-t" | "--tone-map" => {
+                let tone_map = one_of("tone map", &value()?, &shade_tree::TONE_MAP_NAMES)?;
+                set(
+                    &mut options.tone_map,
+                    name,
+                    ToneMap::from_name(&tone_map).unwrap(),
+                )?
+            }
+            "-e" | "--exposure" => {
+                let stops: f64 = number(name, &value()?)?;
+                if !stops.is_finite() {
+                    return Err(format!("invalid value {} for {}", stops, name));
+                }
+                set(&mut options.exposure, name, stops)?
+            }
+            "--tile-size" => set(&mut options.tile_size, name, positive(name, &value()?)?)?,
+            "--tile-order" => {
+                let order = one_of("tile order", &value()?, &shade_tree::TILE_ORDER_NAMES)?;
+                set(
+                    &mut options.tile_order,
+                    name,
+                    TileOrder::from_name(&order).unwrap(),
+                )?
+            }
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+
+    // The old positional form: a scene file or sample count, then the output
+    let mut positional = positional.into_iter();
+    if let Some(first) = positional.next() {
+        if first.ends_with(".toml") {
+            set(&mut options.scene, "scene", PathBuf::from(first))?;
+        } else {
+            let samples = positive("samples per pixel", &first)?;
+            set(&mut options.samples, "samples per pixel", samples)?;
+        }
+    }
+    if let Some(output) = positional.next() {
+        set(&mut options.output, "output", PathBuf::from(output))?;
+    }
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument {}", extra));
+    }
+
+    match (&options.output, &options.format) {
+        (None, Some(_)) => return Err("--format needs an output file".to_string()),
+        (Some(output), None)
+            if !shade_tree::IMAGE_FORMATS.contains(&extension(output).as_str()) =>
+        {
+            return Err(format!(
+                "unknown image format for {}, expected one of: {}",
+                output.display(),
+                shade_tree::IMAGE_FORMATS.join(", ")
+            ));
+        }
+        _ => {}
+    }
+    if let Some(heatmap) = &options.heatmap {
+        if options.output.is_none() {
+            return Err("--heatmap needs an output file".to_string());
+        }
+        if !shade_tree::IMAGE_FORMATS.contains(&extension(heatmap).as_str()) {
+            return Err(format!(
+                "unknown image format for {}, expected one of: {}",
+                heatmap.display(),
+                shade_tree::IMAGE_FORMATS.join(", ")
+            ));
+        }
+    }
+
+    Ok(options)
+}
+
+// The scene's adaptive sampling with the options on top
+fn adaptive(
+    options: &Options,
+    scene: Option<AdaptiveSampling>,
+) -> Result<Option<AdaptiveSampling>, String> {
+    let mut adaptive = scene;
+    if let Some(threshold) = options.adaptive {
+        adaptive = Some(AdaptiveSampling {
+            threshold,
+            ..adaptive.unwrap_or_default()
+        });
+    }
+    if let Some(min_samples) = options.min_samples {
+        adaptive
+            .as_mut()
+            .ok_or_else(|| "--min-samples needs --adaptive".to_string())?
+            .min_samples = min_samples;
+    }
+
+    Ok(adaptive)
+}
+
+// Builds the job for the scene file, or the random scene, with the options
+// on top
+fn build_job(options: &Options) -> Result<RenderJob, String> {
+    let mut job = match &options.scene {
+        Some(path) => {
+            let mut scene = shade_tree::load_scene(path)
+                .map_err(|e| format!("could not load scene {}: {}", path.display(), e))?;
+
+            let settings = &mut scene.settings;
+            let resized = options.width.is_some() || options.height.is_some();
+            settings.width = options.width.unwrap_or(settings.width);
+            settings.height = options.height.unwrap_or(settings.height);
+            settings.samples = options.samples.unwrap_or(settings.samples);
+            settings.max_depth = options.max_depth.unwrap_or(settings.max_depth);
+            settings.seed = options.seed.unwrap_or(settings.seed);
+            settings.tile_size = options.tile_size.unwrap_or(settings.tile_size);
+            settings.tile_order = options.tile_order.unwrap_or(settings.tile_order);
+            settings.adaptive = adaptive(options, settings.adaptive)?;
+            if resized {
+                let aspect = settings.width as f64 / settings.height as f64;
+                scene.camera = scene.camera.with_aspect(aspect);
+            }
+
+            if let Some(name) = &options.integrator {
+                scene.integrator = name.clone();
+            }
+            // Stratified sampling depends on the number of samples
+            let name = match &options.sampler {
+                Some(name) => name.as_str(),
+                None => scene.sampler.name(),
+            };
+            scene.sampler = shade_tree::sampler_from_name(name, scene.settings.samples).unwrap();
+
+            scene.into_job().map_err(|e| e.to_string())?
+        }
+        None => {
+            let defaults = RenderSettings::default();
+            let settings = RenderSettings {
+                width: options.width.unwrap_or(defaults.width),
+                height: options.height.unwrap_or(defaults.height),
+                samples: options.samples.unwrap_or(defaults.samples),
+                max_depth: options.max_depth.unwrap_or(defaults.max_depth),
+                seed: options.seed.unwrap_or(defaults.seed),
+                tile_size: options.tile_size.unwrap_or(defaults.tile_size),
+                tile_order: options.tile_order.unwrap_or(defaults.tile_order),
+                adaptive: adaptive(options, None)?,
+                ..defaults
+            };
+            let integrator = options.integrator.as_deref().unwrap_or("path");
+            let name = options.sampler.as_deref().unwrap_or("independent");
+            let sampler = shade_tree::sampler_from_name(name, settings.samples).unwrap();
+
+            shade_tree::random_scene_job(settings, integrator)
+                .map_err(|e| e.to_string())?
+                .with_sampler(sampler)
         }
-    } else {
-        DEFAULT_SAMPLES
     };
 
-    if args.len() > 2 {
-        output_file(samples, args[2].clone());
-    } else {
-        window_run(samples);
+    let mut tone_mapping = job.settings().tone_mapping;
+    if let Some(operator) = options.tone_map {
+        tone_mapping.operator = operator;
+    }
+    if let Some(stops) = options.exposure {
+        tone_mapping.exposure = stops;
+    }
+    job.set_tone_mapping(tone_mapping);
+
+    Ok(job)
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    if let Some(threads) = options.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|e| format!("could not start {} threads: {}", threads, e))?;
+    }
+
+    let mut job = build_job(options)?;
+
+    match &options.output {
+        Some(output) => {
+            job.render();
+            let tone_mapping = job.settings().tone_mapping;
+            output_file(
+                &job.framebuffer(),
+                &tone_mapping,
+                output,
+                options.format.as_deref(),
+            )
+            .map_err(|e| format!("could not write {}: {}", output.display(), e))?;
+
+            match &options.heatmap {
+                Some(heatmap) => {
+                    output_file(&job.heatmap(), &ToneMapping::default(), heatmap, None)
+                        .map_err(|e| format!("could not write {}: {}", heatmap.display(), e))
+                }
+                None => Ok(()),
+            }
+        }
+        None => window_run(job),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
     };
+    if options.help {
+        print!("{}", USAGE);
+        return;
+    }
+
+    println!("Shade Tree");
+    if let Err(e) = run(&options) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::{adaptive, parse_args};
+    use shade_tree::{AdaptiveSampling, TileOrder, ToneMap};
+    use std::path::PathBuf;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn flags() {
+        let options = parse_args(&args(
+            "--scene scenes/lamp.toml -o out.img --format=exr -W 320 --height=200 \
+             --spp 8 -d 5 --seed 7 -j 2 -i mis -t aces --exposure -1.5 \
+             --tile-size 16 --tile-order=hilbert --sampler sobol -a 0.02 --min-samples 4 \
+             --heatmap heat.png",
+        ))
+        .unwrap();
+
+        assert_eq!(options.scene, Some(PathBuf::from("scenes/lamp.toml")));
+        assert_eq!(options.output, Some(PathBuf::from("out.img")));
+        assert_eq!(options.format.as_deref(), Some("exr"));
+        assert_eq!((options.width, options.height), (Some(320), Some(200)));
+        assert_eq!(options.samples, Some(8));
+        assert_eq!(options.max_depth, Some(5));
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.threads, Some(2));
+        assert_eq!(options.integrator.as_deref(), Some("mis"));
+        assert_eq!(options.tone_map, Some(ToneMap::Aces));
+        assert_eq!(options.exposure, Some(-1.5));
+        assert_eq!(options.tile_size, Some(16));
+        assert_eq!(options.tile_order, Some(TileOrder::Hilbert));
+        assert_eq!(options.sampler.as_deref(), Some("sobol"));
+        assert_eq!(options.adaptive, Some(0.02));
+        assert_eq!(options.min_samples, Some(4));
+        assert_eq!(options.heatmap, Some(PathBuf::from("heat.png")));
+        assert!(!options.help);
+
+        assert!(parse_args(&args("--help")).unwrap().help);
+        assert!(parse_args(&[]).unwrap().output.is_none());
+    }
+
+    #[test]
+    fn positional_arguments() {
+        let options = parse_args(&args("100 out.png")).unwrap();
+        assert_eq!(options.samples, Some(100));
+        assert_eq!(options.output, Some(PathBuf::from("out.png")));
+
+        let options = parse_args(&args("scenes/simple.toml --spp 4")).unwrap();
+        assert_eq!(options.scene, Some(PathBuf::from("scenes/simple.toml")));
+        assert_eq!(options.samples, Some(4));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let error = |line| parse_args(&args(line)).unwrap_err();
+
+        assert!(error("lots").contains("samples per pixel"));
+        assert!(error("0").contains("at least 1"));
+        assert!(error("--width 0").contains("at least 1"));
+        assert!(error("--height tall").contains("--height"));
+        assert!(error("--spp").contains("needs a value"));
+        assert!(error("--integrator whitted").contains("mis"));
+        assert!(error("--tone-map filmic").contains("reinhard"));
+        assert!(error("--exposure inf").contains("--exposure"));
+        assert!(error("--tile-size 0").contains("at least 1"));
+        assert!(error("--tile-order random").contains("spiral"));
+        assert!(error("--sampler random").contains("halton"));
+        assert!(error("--adaptive 0").contains("positive"));
+        assert!(error("--heatmap heat.png").contains("output"));
+        assert!(error("-o out.png --heatmap heat.gif").contains("heat.gif"));
+        assert!(error("--bounces 3").contains("unknown option"));
+        assert!(error("10 out.png extra").contains("extra"));
+        assert!(error("10 --spp 20").contains("more than once"));
+        assert!(error("out.bmp -o out.bmp").contains("samples per pixel"));
+        assert!(error("-o out.bmp").contains("out.bmp"));
+        assert!(error("--format png").contains("output"));
+        assert!(error("-o out.png -f gif").contains("gif"));
+    }
+
+    #[test]
+    fn adaptive_options() {
+        let scene = AdaptiveSampling {
+            threshold: 0.1,
+            min_samples: 32,
+        };
+        let options = |line| parse_args(&args(line)).unwrap();
+
+        assert_eq!(adaptive(&options(""), None), Ok(None));
+        assert_eq!(adaptive(&options(""), Some(scene)), Ok(Some(scene)));
+        assert_eq!(
+            adaptive(&options("-a 0.02"), Some(scene)),
+            Ok(Some(AdaptiveSampling {
+                threshold: 0.02,
+                min_samples: 32
+            }))
+        );
+        assert_eq!(
+            adaptive(&options("-a 0.02 --min-samples 8"), None),
+            Ok(Some(AdaptiveSampling {
+                threshold: 0.02,
+                min_samples: 8
+            }))
+        );
+        assert!(adaptive(&options("--min-samples 8"), None)
+            .unwrap_err()
+            .contains("--adaptive"));
+    }
 }
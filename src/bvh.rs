@@ -0,0 +1,269 @@
+use crate::aabb::Aabb;
+use crate::ray::Ray;
+use crate::vec::Vec3f;
+
+// Number of buckets the centroids are binned into when evaluating the
+// surface area heuristic
+const SAH_BINS: usize = 12;
+// Leaves with more primitives than this are always split if possible
+const MAX_LEAF_SIZE: usize = 4;
+// Cost of visiting an interior node relative to intersecting a primitive
+const TRAVERSAL_COST: f64 = 0.125;
+
+#[derive(Copy, Clone)]
+enum NodeKind {
+    Leaf { first: usize, count: usize },
+    // The left child is always stored right after its parent
+    Interior { right: usize, axis: usize },
+}
+
+#[derive(Copy, Clone)]
+struct BvhNode {
+    bounds: Aabb,
+    kind: NodeKind,
+}
+
+struct BuildPrim {
+    bounds: Aabb,
+    centroid: Vec3f,
+    index: usize,
+}
+
+// Bounding volume hierarchy over a list of primitives, flattened into a
+// vector of nodes. It only knows about the bounding boxes, the caller maps
+// the indices back to its own primitives when traversing.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+}
+
+impl Bvh {
+    // Builds the tree with the surface area heuristic. The index of each box
+    // in `bounds` is what gets handed back during traversal.
+    pub fn new(bounds: &[Aabb]) -> Bvh {
+        let mut prims: Vec<BuildPrim> = bounds
+            .iter()
+            .enumerate()
+            .map(|(index, b)| BuildPrim {
+                bounds: *b,
+                centroid: b.centroid(),
+                index,
+            })
+            .collect();
+
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * prims.len()),
+            indices: Vec::with_capacity(prims.len()),
+        };
+        if !prims.is_empty() {
+            bvh.build(&mut prims);
+        }
+
+        bvh
+    }
+
+    // Replaces every primitive index `i` with `map[i]`
+    pub fn remap(mut self, map: &[usize]) -> Bvh {
+        for i in self.indices.iter_mut() {
+            *i = map[*i];
+        }
+
+        self
+    }
+
+    fn push_leaf(&mut self, node: usize, prims: &[BuildPrim]) {
+        let first = self.indices.len();
+        self.indices.extend(prims.iter().map(|p| p.index));
+        self.nodes[node].kind = NodeKind::Leaf {
+            first,
+            count: prims.len(),
+        };
+    }
+
+    fn build(&mut self, prims: &mut [BuildPrim]) -> usize {
+        let bounds = prims
+            .iter()
+            .fold(Aabb::empty(), |acc, p| acc.union(&p.bounds));
+        let node = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds,
+            kind: NodeKind::Leaf { first: 0, count: 0 },
+        });
+
+        let count = prims.len();
+        if count == 1 {
+            self.push_leaf(node, prims);
+            return node;
+        }
+
+        let centroid_bounds = prims
+            .iter()
+            .fold(Aabb::empty(), |acc, p| acc.grow(p.centroid));
+
+        let (mid, axis) = match find_sah_split(prims, &bounds, &centroid_bounds) {
+            Some((axis, bin, cost)) => {
+                if cost >= count as f64 && count <= MAX_LEAF_SIZE {
+                    self.push_leaf(node, prims);
+                    return node;
+                }
+
+                let min = centroid_bounds.min().get(axis);
+                let extent = centroid_bounds.extent().get(axis);
+                let mid = partition(prims, |p| {
+                    bin_index(p.centroid.get(axis), min, extent) <= bin
+                });
+                if mid == 0 || mid == count {
+                    (count / 2, axis)
+                } else {
+                    (mid, axis)
+                }
+            }
+            None => {
+                // All centroids are in the same spot, there is nothing the
+                // heuristic can do so just split them in half
+                if count <= MAX_LEAF_SIZE {
+                    self.push_leaf(node, prims);
+                    return node;
+                }
+                (count / 2, bounds.largest_axis())
+            }
+        };
+
+        let (left, right) = prims.split_at_mut(mid);
+        self.build(left);
+        let right = self.build(right);
+        self.nodes[node].kind = NodeKind::Interior { right, axis };
+
+        node
+    }
+
+    // Walks the tree front to back. `hit_prim` gets the index of a primitive
+    // and the closest distance found so far, and returns the distance of a
+    // closer hit if there is one.
+    pub fn hit<F>(&self, ray: &Ray, t_min: f64, t_max: f64, mut hit_prim: F) -> bool
+    where
+        F: FnMut(usize, f64) -> Option<f64>,
+    {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let dir = ray.direction();
+        let inv_dir = Vec3f::new(1.0 / dir.x(), 1.0 / dir.y(), 1.0 / dir.z());
+
+        let mut hit_anything = false;
+        let mut closest = t_max;
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+        stack.push(0);
+
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if !node.bounds.hit(ray, inv_dir, t_min, closest) {
+                continue;
+            }
+
+            match node.kind {
+                NodeKind::Leaf { first, count } => {
+                    for &prim in &self.indices[first..first + count] {
+                        if let Some(t) = hit_prim(prim, closest) {
+                            hit_anything = true;
+                            closest = t;
+                        }
+                    }
+                }
+                NodeKind::Interior { right, axis } => {
+                    // Push the far child first so the near one is visited
+                    // first and can shrink `closest` early
+                    if dir.get(axis) < 0.0 {
+                        stack.push(idx + 1);
+                        stack.push(right);
+                    } else {
+                        stack.push(right);
+                        stack.push(idx + 1);
+                    }
+                }
+            }
+        }
+
+        hit_anything
+    }
+}
+
+fn bin_index(c: f64, min: f64, extent: f64) -> usize {
+    let b = ((c - min) / extent * SAH_BINS as f64) as usize;
+    b.min(SAH_BINS - 1)
+}
+
+// Returns the axis, the last bin of the left side and the cost of the
+// cheapest split, or None if the centroids can't be told apart on any axis
+fn find_sah_split(
+    prims: &[BuildPrim],
+    bounds: &Aabb,
+    centroid_bounds: &Aabb,
+) -> Option<(usize, usize, f64)> {
+    let parent_area = bounds.surface_area();
+    let mut best: Option<(usize, usize, f64)> = None;
+
+    for axis in 0..3 {
+        let min = centroid_bounds.min().get(axis);
+        let extent = centroid_bounds.extent().get(axis);
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let mut bin_bounds = [Aabb::empty(); SAH_BINS];
+        let mut bin_count = [0usize; SAH_BINS];
+        for p in prims {
+            let b = bin_index(p.centroid.get(axis), min, extent);
+            bin_bounds[b] = bin_bounds[b].union(&p.bounds);
+            bin_count[b] += 1;
+        }
+
+        // Sweep from the right to know the area and count of every suffix
+        let mut right_area = [0.0; SAH_BINS];
+        let mut right_count = [0usize; SAH_BINS];
+        let mut acc = Aabb::empty();
+        let mut n = 0;
+        for b in (1..SAH_BINS).rev() {
+            acc = acc.union(&bin_bounds[b]);
+            n += bin_count[b];
+            right_area[b] = acc.surface_area();
+            right_count[b] = n;
+        }
+
+        let mut acc = Aabb::empty();
+        let mut n = 0;
+        for b in 0..SAH_BINS - 1 {
+            acc = acc.union(&bin_bounds[b]);
+            n += bin_count[b];
+            if n == 0 || right_count[b + 1] == 0 {
+                continue;
+            }
+
+            let cost = TRAVERSAL_COST
+                + (acc.surface_area() * n as f64 + right_area[b + 1] * right_count[b + 1] as f64)
+                    / parent_area;
+            if best.is_none_or(|(_, _, c)| cost < c) {
+                best = Some((axis, b, cost));
+            }
+        }
+    }
+
+    best
+}
+
+// Moves every primitive matching `pred` to the front, returns how many did
+fn partition<F>(prims: &mut [BuildPrim], pred: F) -> usize
+where
+    F: Fn(&BuildPrim) -> bool,
+{
+    let mut first = 0;
+    for i in 0..prims.len() {
+        if pred(&prims[i]) {
+            prims.swap(first, i);
+            first += 1;
+        }
+    }
+
+    first
+}
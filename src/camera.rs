@@ -1,8 +1,7 @@
 use crate::ray::Ray;
+use crate::sampler::SampleStream;
 use crate::vec::Vec3f;
-use crate::visible::{HitRecord, Visible};
-use crate::world::World;
-use rand::Rng;
+use std::f64::consts::PI;
 
 #[derive(Copy, Clone)]
 pub struct Camera {
@@ -13,6 +12,9 @@ pub struct Camera {
     lens_radius: f64,
     u: Vec3f,
     v: Vec3f,
+    // Rays are spread evenly over the time the shutter is open
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 impl Camera {
@@ -49,61 +51,133 @@ impl Camera {
             lens_radius,
             u,
             v,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = random_in_unit_disk() * self.lens_radius;
-        let offset = self.u * rd.x() + self.v * rd.y();
+    // Moving objects blur over the time between `open` and `close`
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Camera {
+        self.shutter_open = open;
+        self.shutter_close = close;
+        self
+    }
+
+    // Widens or narrows the view to a new width / height, keeping the
+    // vertical field of view and where the camera looks
+    pub fn with_aspect(mut self, aspect: f64) -> Camera {
+        let center = self.lower_left_corner + self.horizontal * 0.5 + self.vertical * 0.5;
+        self.horizontal = self.u * (self.vertical.length() * aspect);
+        self.lower_left_corner = center - self.horizontal * 0.5 - self.vertical * 0.5;
+        self
+    }
+
+    // Ray through (s, t) on the image, from a point on the lens and a time
+    // the shutter is open picked with the sampler
+    pub fn get_ray(&self, s: f64, t: f64, stream: &mut SampleStream) -> Ray {
+        let (x, y) = concentric_disk(stream.get_2d());
+        let offset = self.u * (x * self.lens_radius) + self.v * (y * self.lens_radius);
+
+        let time = if self.shutter_close > self.shutter_open {
+            self.shutter_open + (self.shutter_close - self.shutter_open) * stream.get_1d()
+        } else {
+            self.shutter_open
+        };
 
-        Ray::new(
+        Ray::at_time(
             self.origin + offset,
             self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
+            time,
         )
     }
 }
 
-pub fn color(r: Ray, vis_obj: &World, depth: usize) -> Vec3f {
-    let mut rec = HitRecord::default();
-
-    if vis_obj.hit(r, 0.0001, std::f64::MAX, &mut rec) {
-        let mut scattered = Ray::default();
-        let mut attenuation = Vec3f::default();
-        let rec_cp = HitRecord {
-            p: rec.p,
-            normal: rec.normal,
-            t: rec.t,
-            material: rec.material,
-        };
-
-        if depth < 50
-            && rec
-                .material
-                .scatter(&r, &rec_cp, &mut attenuation, &mut scattered)
-        {
-            return attenuation * color(scattered, vis_obj, depth + 1);
-        } else {
-            return Vec3f::default();
-        }
+// Shirley and Chiu's mapping of the unit square onto the unit disk, which
+// squeezes squares into rings without tearing them apart, so evenly spread
+// samples stay evenly spread
+fn concentric_disk((u, v): (f64, f64)) -> (f64, f64) {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
     }
 
-    let t = 0.5 * (r.direction().y() + 1.0);
-
-    // Linear interpolation: blended value = ((1 - t) * start_value) + (t * end_value)
-    Vec3f::new(1.0, 1.0, 1.0) * (1.0 - t) + Vec3f::new(0.5, 0.7, 1.0) * t
+    let (r, phi) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+    (r * phi.cos(), r * phi.sin())
 }
 
-fn random_in_unit_disk() -> Vec3f {
-    let mut rng = rand::thread_rng();
-    let mut p: Vec3f;
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::camera::{concentric_disk, Camera};
+    use crate::sampler::{Independent, SampleStream};
+    use crate::vec::Vec3f;
+
+    fn camera() -> Camera {
+        Camera::new(
+            Vec3f::new(0.0, 0.0, 1.0),
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        )
+    }
 
-    while {
-        let rnd_x = rng.gen_range(0.0, 1.0);
-        let rnd_y = rng.gen_range(0.0, 1.0);
-        p = Vec3f::new(rnd_x, rnd_y, 0.0) * 2.0;
+    #[test]
+    fn change_aspect() {
+        let mut stream = SampleStream::new(&Independent, 0, 0, 0);
+        let wide = Camera::new(
+            Vec3f::new(0.0, 0.0, 1.0),
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+            0.0,
+            1.0,
+        );
+        let changed = camera().with_aspect(2.0);
+
+        for (u, v) in [(0.0, 0.0), (0.5, 0.5), (1.0, 0.25)].iter() {
+            let a = wide.get_ray(*u, *v, &mut stream).direction();
+            let b = changed.get_ray(*u, *v, &mut stream).direction();
+            assert!((a - b).length() < 1e-12);
+        }
+    }
 
-        p.dot(&p) >= 1.0
-    } {}
+    #[test]
+    fn shutter_times() {
+        let mut stream = SampleStream::new(&Independent, 0, 0, 0);
+        assert_eq!(camera().get_ray(0.5, 0.5, &mut stream).time(), 0.0);
+
+        let camera = camera().with_shutter(0.25, 0.75);
+        let times: Vec<f64> = (0..1000)
+            .map(|_| camera.get_ray(0.5, 0.5, &mut stream).time())
+            .collect();
+        assert!(times.iter().all(|t| (0.25..0.75).contains(t)));
+        let mean = times.iter().sum::<f64>() / times.len() as f64;
+        assert!((mean - 0.5).abs() < 0.02);
+    }
 
-    p
+    #[test]
+    fn lens_samples_fill_the_disk() {
+        // Each quarter of the square lands on its own quarter of the disk
+        let mut quadrants = [0; 4];
+        for i in 0..32 {
+            for j in 0..32 {
+                let u = (i as f64 + 0.5) / 32.0;
+                let v = (j as f64 + 0.5) / 32.0;
+                let (x, y) = concentric_disk((u, v));
+                assert!(x * x + y * y <= 1.0 + 1e-12);
+                quadrants[(x > 0.0) as usize + 2 * (y > 0.0) as usize] += 1;
+            }
+        }
+        assert_eq!(quadrants, [256; 4]);
+        assert_eq!(concentric_disk((0.5, 0.5)), (0.0, 0.0));
+        assert!((concentric_disk((1.0, 0.5)).0 - 1.0).abs() < 1e-12);
+    }
 }
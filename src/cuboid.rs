@@ -0,0 +1,139 @@
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::quad::Quad;
+use crate::ray::Ray;
+use crate::vec::Vec3f;
+use crate::visible::{HitRecord, Visible};
+
+// Axis aligned box made of six quads facing out. Rotate it with a
+// Transform.
+#[derive(Clone)]
+pub struct Cuboid {
+    sides: Vec<Quad>,
+    bbox: Aabb,
+}
+
+impl Cuboid {
+    // Between two opposite corners
+    pub fn new(a: Vec3f, b: Vec3f) -> Cuboid {
+        let min = Vec3f::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Vec3f::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        let dx = Vec3f::new(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3f::new(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3f::new(0.0, 0.0, max.z() - min.z());
+
+        let sides = vec![
+            // Front, right, back, left, top and bottom
+            Quad::new(Vec3f::new(min.x(), min.y(), max.z()), dx, dy),
+            Quad::new(Vec3f::new(max.x(), min.y(), max.z()), dz * -1.0, dy),
+            Quad::new(Vec3f::new(max.x(), min.y(), min.z()), dx * -1.0, dy),
+            Quad::new(min, dz, dy),
+            Quad::new(Vec3f::new(min.x(), max.y(), max.z()), dx, dz * -1.0),
+            Quad::new(min, dx, dz),
+        ];
+
+        let bbox = sides
+            .iter()
+            .filter_map(|s| s.bounding_box())
+            .fold(Aabb::empty(), |b, s| b.union(&s));
+
+        Cuboid { sides, bbox }
+    }
+}
+
+impl Visible for Cuboid {
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        let mut closest = t_max;
+        for side in self.sides.iter() {
+            if side.hit(ray, t_min, closest, rec) {
+                closest = rec.t;
+            }
+        }
+
+        closest < t_max
+    }
+
+    fn set_material(&mut self, m: Material) {
+        for side in self.sides.iter_mut() {
+            side.set_material(m.clone());
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::cuboid::Cuboid;
+    use crate::ray::Ray;
+    use crate::vec::Vec3f;
+    use crate::visible::{HitRecord, Visible};
+
+    fn cube() -> Cuboid {
+        Cuboid::new(Vec3f::new(1.0, 1.0, 1.0), Vec3f::new(-1.0, -1.0, -1.0))
+    }
+
+    #[test]
+    fn cuboid_hit() {
+        let cube = cube();
+        let mut rec = HitRecord::default();
+
+        // Every side from the outside faces the ray
+        let axes = [
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            Vec3f::new(0.0, 0.0, 1.0),
+        ];
+        for axis in axes.iter() {
+            for sign in [-1.0, 1.0].iter() {
+                let outward = *axis * *sign;
+                let offset = Vec3f::new(0.1, 0.2, 0.3);
+                let origin = outward * 3.0 + offset - *axis * offset.dot(axis);
+                let ray = Ray::new(origin, outward * -1.0);
+                assert!(cube.hit(ray, 0.0001, f64::MAX, &mut rec));
+                assert_eq!(rec.t, 2.0);
+                assert_eq!(rec.normal, outward);
+            }
+        }
+
+        // The closest side wins, and from inside the far one is hit
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert!(cube.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.p, Vec3f::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn cuboid_miss() {
+        let cube = cube();
+        let mut rec = HitRecord::default();
+
+        let ray = Ray::new(Vec3f::new(3.0, 1.5, 0.0), Vec3f::new(-1.0, 0.0, 0.0));
+        assert!(!cube.hit(ray, 0.0001, f64::MAX, &mut rec));
+        let ray = Ray::new(Vec3f::new(3.0, 0.0, 0.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert!(!cube.hit(ray, 0.0001, f64::MAX, &mut rec));
+        let ray = Ray::new(Vec3f::new(3.0, 0.0, 0.0), Vec3f::new(-1.0, 0.0, 0.0));
+        assert!(!cube.hit(ray, 0.0001, 1.5, &mut rec));
+
+        // Running along the top face, and along its front edge. The faces
+        // parallel to the ray are skipped, so only the rim of the side it
+        // comes in through is touched.
+        for origin in [Vec3f::new(-3.0, 1.0, 0.0), Vec3f::new(-3.0, 1.0, 1.0)].iter() {
+            let ray = Ray::new(*origin, Vec3f::new(1.0, 0.0, 0.0));
+            assert!(cube.hit(ray, 0.0001, f64::MAX, &mut rec));
+            assert_eq!(rec.t, 2.0);
+            assert_eq!(rec.normal, Vec3f::new(-1.0, 0.0, 0.0));
+
+            // Just outside the face or edge it's a miss
+            let ray = Ray::new(
+                *origin + Vec3f::new(0.0, 0.001, 0.0),
+                Vec3f::new(1.0, 0.0, 0.0),
+            );
+            assert!(!cube.hit(ray, 0.0001, f64::MAX, &mut rec));
+        }
+        let ray = Ray::new(Vec3f::new(-3.0, 1.0, 1.001), Vec3f::new(1.0, 0.0, 0.0));
+        assert!(!cube.hit(ray, 0.0001, f64::MAX, &mut rec));
+    }
+}
@@ -0,0 +1,321 @@
+use crate::aabb::Aabb;
+use crate::disk::Disk;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec::Vec3f;
+use crate::visible::{HitRecord, Visible};
+use std::f64::consts::PI;
+
+// Roots of a t^2 + 2 half_b t + c = 0, nearest first. None when the ray
+// misses, or only touches, the surface.
+fn quadratic(a: f64, half_b: f64, c: f64) -> Option<(f64, f64)> {
+    if a.abs() < 1e-12 {
+        // Parallel to one of the lines of a cone, which it crosses once
+        if half_b == 0.0 {
+            return None;
+        }
+        let t = -c / (2.0 * half_b);
+        return Some((t, t));
+    }
+
+    let discriminant = half_b * half_b - a * c;
+    if discriminant <= 0.0 {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    let (t0, t1) = ((-half_b - root) / a, (-half_b + root) / a);
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+// Around the Y axis like spheres for u, and from the bottom up for v
+fn side_uv(local: Vec3f, height: f64) -> (f64, f64) {
+    let phi = (-local.z()).atan2(local.x()) + PI;
+
+    (phi / (2.0 * PI), local.y() / height)
+}
+
+// Nearest root of the side between t_min and t_max whose point is within
+// the height of the shape, in coordinates relative to the base
+fn side_hit(
+    roots: Option<(f64, f64)>,
+    origin: Vec3f,
+    dir: Vec3f,
+    height: f64,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, Vec3f)> {
+    let (t0, t1) = roots?;
+    [t0, t1]
+        .iter()
+        .filter(|t| **t > t_min && **t < t_max)
+        .map(|t| (*t, origin + dir * *t))
+        .find(|(_, local)| (0.0..=height).contains(&local.y()))
+}
+
+fn bounds(base: Vec3f, radius: f64, height: f64) -> Aabb {
+    Aabb::new(
+        base - Vec3f::new(radius, 0.0, radius),
+        base + Vec3f::new(radius, height, radius),
+    )
+}
+
+// Closed cylinder standing on a disk centered at `base`, along the Y axis.
+// Tilt it with a Transform.
+#[derive(Clone)]
+pub struct Cylinder {
+    base: Vec3f,
+    radius: f64,
+    height: f64,
+    // Bottom and top
+    caps: [Disk; 2],
+    material: Material,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3f, radius: f64, height: f64) -> Cylinder {
+        let up = Vec3f::new(0.0, 1.0, 0.0);
+        let caps = [
+            Disk::new(base, up * -1.0, radius),
+            Disk::new(base + up * height, up, radius),
+        ];
+
+        Cylinder {
+            base,
+            radius,
+            height,
+            caps,
+            material: Material::default(),
+        }
+    }
+}
+
+impl Visible for Cylinder {
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        let o = ray.origin() - self.base;
+        let d = ray.direction();
+        let roots = quadratic(
+            d.x() * d.x() + d.z() * d.z(),
+            o.x() * d.x() + o.z() * d.z(),
+            o.x() * o.x() + o.z() * o.z() - self.radius * self.radius,
+        );
+
+        let mut closest = t_max;
+        if let Some((t, local)) = side_hit(roots, o, d, self.height, t_min, t_max) {
+            let (u, v) = side_uv(local, self.height);
+            rec.t = t;
+            rec.p = ray.point_at(t);
+            rec.normal = Vec3f::new(local.x(), 0.0, local.z()) / self.radius;
+            rec.u = u;
+            rec.v = v;
+            rec.material = &self.material;
+            closest = t;
+        }
+        for cap in self.caps.iter() {
+            if cap.hit(ray, t_min, closest, rec) {
+                closest = rec.t;
+            }
+        }
+
+        closest < t_max
+    }
+
+    fn set_material(&mut self, m: Material) {
+        for cap in self.caps.iter_mut() {
+            cap.set_material(m.clone());
+        }
+        self.material = m;
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(bounds(self.base, self.radius, self.height))
+    }
+}
+
+// Cone standing on a disk centered at `base`, with its tip `height` above
+#[derive(Clone)]
+pub struct Cone {
+    base: Vec3f,
+    radius: f64,
+    height: f64,
+    bottom: Disk,
+    material: Material,
+}
+
+impl Cone {
+    pub fn new(base: Vec3f, radius: f64, height: f64) -> Cone {
+        Cone {
+            base,
+            radius,
+            height,
+            bottom: Disk::new(base, Vec3f::new(0.0, -1.0, 0.0), radius),
+            material: Material::default(),
+        }
+    }
+}
+
+impl Visible for Cone {
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        let o = ray.origin() - self.base;
+        let d = ray.direction();
+        // The radius shrinks by `slope` for every unit up:
+        // x^2 + z^2 = (radius - slope * y)^2
+        let slope = self.radius / self.height;
+        let m = self.radius - slope * o.y();
+        let roots = quadratic(
+            d.x() * d.x() + d.z() * d.z() - slope * slope * d.y() * d.y(),
+            o.x() * d.x() + o.z() * d.z() + m * slope * d.y(),
+            o.x() * o.x() + o.z() * o.z() - m * m,
+        );
+
+        let mut closest = t_max;
+        if let Some((t, local)) = side_hit(roots, o, d, self.height, t_min, t_max) {
+            let (u, v) = side_uv(local, self.height);
+            let mut normal = Vec3f::new(
+                local.x(),
+                slope * (self.radius - slope * local.y()),
+                local.z(),
+            );
+            // Right at the tip there is no slope to go by
+            if normal.squared_length() == 0.0 {
+                normal = Vec3f::new(0.0, 1.0, 0.0);
+            }
+            rec.t = t;
+            rec.p = ray.point_at(t);
+            rec.normal = normal.make_unit_vector();
+            rec.u = u;
+            rec.v = v;
+            rec.material = &self.material;
+            closest = t;
+        }
+        if self.bottom.hit(ray, t_min, closest, rec) {
+            closest = rec.t;
+        }
+
+        closest < t_max
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.bottom.set_material(m.clone());
+        self.material = m;
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(bounds(self.base, self.radius, self.height))
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::cylinder::{Cone, Cylinder};
+    use crate::ray::Ray;
+    use crate::vec::{close, Vec3f};
+    use crate::visible::{HitRecord, Visible};
+
+    // Radius 1 from y = 0 to y = 2, centered on the Y axis
+    fn cylinder() -> Cylinder {
+        Cylinder::new(Vec3f::new(0.0, 0.0, 0.0), 1.0, 2.0)
+    }
+
+    #[test]
+    fn cylinder_hit() {
+        let cylinder = cylinder();
+        let mut rec = HitRecord::default();
+
+        let ray = Ray::new(Vec3f::new(0.0, 0.5, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(cylinder.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!((rec.t - 4.0).abs() < 1e-12);
+        assert!(close(rec.normal, Vec3f::new(0.0, 0.0, 1.0)));
+        assert_eq!((rec.u, rec.v), (0.25, 0.25));
+
+        // Caps
+        let ray = Ray::new(Vec3f::new(0.5, 5.0, 0.0), Vec3f::new(0.0, -1.0, 0.0));
+        assert!(cylinder.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 3.0);
+        assert_eq!(rec.normal, Vec3f::new(0.0, 1.0, 0.0));
+        let ray = Ray::new(Vec3f::new(0.5, -1.0, 0.0), Vec3f::new(0.0, 1.0, 0.0));
+        assert!(cylinder.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.normal, Vec3f::new(0.0, -1.0, 0.0));
+
+        // From inside, the far wall
+        let ray = Ray::new(Vec3f::new(0.0, 1.0, 0.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert!(cylinder.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!(close(rec.p, Vec3f::new(1.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn cylinder_miss() {
+        let cylinder = cylinder();
+        let mut rec = HitRecord::default();
+
+        // Over the top and beside it
+        let ray = Ray::new(Vec3f::new(0.0, 2.5, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!cylinder.hit(ray, 0.0001, f64::MAX, &mut rec));
+        let ray = Ray::new(Vec3f::new(1.5, 1.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!cylinder.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Tangent to the side
+        let ray = Ray::new(Vec3f::new(1.0, 1.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!cylinder.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Parallel to the axis, outside
+        let ray = Ray::new(Vec3f::new(2.0, 5.0, 0.0), Vec3f::new(0.0, -1.0, 0.0));
+        assert!(!cylinder.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        let b = cylinder.bounding_box().unwrap();
+        assert_eq!(b.min(), Vec3f::new(-1.0, 0.0, -1.0));
+        assert_eq!(b.max(), Vec3f::new(1.0, 2.0, 1.0));
+    }
+
+    // Radius 1 at y = 0, tip at y = 1
+    fn cone() -> Cone {
+        Cone::new(Vec3f::new(0.0, 0.0, 0.0), 1.0, 1.0)
+    }
+
+    #[test]
+    fn cone_hit() {
+        let cone = cone();
+        let mut rec = HitRecord::default();
+
+        // Halfway up the radius is 0.5, and the side leans at 45 degrees
+        let ray = Ray::new(Vec3f::new(5.0, 0.5, 0.0), Vec3f::new(-1.0, 0.0, 0.0));
+        assert!(cone.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!((rec.t - 4.5).abs() < 1e-12);
+        let diagonal = 0.5_f64.sqrt();
+        assert!(close(rec.normal, Vec3f::new(diagonal, diagonal, 0.0)));
+        assert!((rec.v - 0.5).abs() < 1e-12);
+
+        // Straight down next to the tip and up into the bottom
+        let ray = Ray::new(Vec3f::new(0.2, 3.0, 0.0), Vec3f::new(0.0, -1.0, 0.0));
+        assert!(cone.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!((rec.t - 2.2).abs() < 1e-12);
+        assert!(close(rec.normal, Vec3f::new(diagonal, diagonal, 0.0)));
+        let ray = Ray::new(Vec3f::new(0.5, -1.0, 0.0), Vec3f::new(0.0, 1.0, 0.0));
+        assert!(cone.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.normal, Vec3f::new(0.0, -1.0, 0.0));
+
+        // Parallel to the side crosses it once
+        let ray = Ray::new(Vec3f::new(-0.5, 1.0, 0.0), Vec3f::new(1.0, -1.0, 0.0));
+        assert!(cone.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!(close(rec.p, Vec3f::new(-0.25, 0.75, 0.0)));
+    }
+
+    #[test]
+    fn cone_miss() {
+        let cone = cone();
+        let mut rec = HitRecord::default();
+
+        // Wider than the cone is at that height
+        let ray = Ray::new(Vec3f::new(0.6, 0.5, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!cone.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // The mirrored cone above the tip isn't part of it
+        let ray = Ray::new(Vec3f::new(5.0, 1.5, 0.0), Vec3f::new(-1.0, 0.0, 0.0));
+        assert!(!cone.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Tangent to the side, touching it at a single point
+        let ray = Ray::new(Vec3f::new(0.5, 0.5, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!cone.hit(ray, 0.0001, f64::MAX, &mut rec));
+    }
+}
@@ -0,0 +1,177 @@
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::quad::{BOX_PADDING, PARALLEL_EPSILON};
+use crate::ray::Ray;
+use crate::rng::Pcg32;
+use crate::vec::Vec3f;
+use crate::visible::{HitRecord, Visible};
+use rand::Rng;
+use std::f64::consts::PI;
+
+// Flat circle facing `normal`. u goes around the center and v from the
+// center out to the rim.
+#[derive(Clone)]
+pub struct Disk {
+    center: Vec3f,
+    normal: Vec3f,
+    radius: f64,
+    // Where u starts and which way it turns
+    tangent: Vec3f,
+    bitangent: Vec3f,
+    material: Material,
+}
+
+impl Disk {
+    pub fn new(center: Vec3f, normal: Vec3f, radius: f64) -> Disk {
+        let normal = normal.make_unit_vector();
+        let (tangent, bitangent) = normal.orthonormal_basis();
+
+        Disk {
+            center,
+            normal,
+            radius,
+            tangent,
+            bitangent,
+            material: Material::default(),
+        }
+    }
+}
+
+impl Visible for Disk {
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        let denom = self.normal.dot(&ray.direction());
+        if denom.abs() < PARALLEL_EPSILON {
+            return false;
+        }
+
+        let t = self.normal.dot(&(self.center - ray.origin())) / denom;
+        if t >= t_max || t <= t_min {
+            return false;
+        }
+
+        let p = ray.point_at(t);
+        let planar = p - self.center;
+        let dist_squared = planar.squared_length();
+        if dist_squared > self.radius * self.radius {
+            return false;
+        }
+
+        let angle = planar.dot(&self.bitangent).atan2(planar.dot(&self.tangent));
+        rec.t = t;
+        rec.p = p;
+        rec.normal = self.normal;
+        rec.u = angle.rem_euclid(2.0 * PI) / (2.0 * PI);
+        rec.v = dist_squared.sqrt() / self.radius;
+        rec.material = &self.material;
+
+        true
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // How far the rim reaches along each axis
+        let n = self.normal;
+        let reach = |c: f64| self.radius * (1.0 - c * c).max(0.0).sqrt() + BOX_PADDING;
+        let extent = Vec3f::new(reach(n.x()), reach(n.y()), reach(n.z()));
+
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    // Uniformly samples a point over the area of the disk
+    fn sample_direction(&self, origin: Vec3f, rng: &mut Pcg32) -> Option<Vec3f> {
+        let r = self.radius * rng.gen_range(0.0_f64, 1.0).sqrt();
+        let angle = 2.0 * PI * rng.gen_range(0.0, 1.0);
+        let p = self.center + self.tangent * (r * angle.cos()) + self.bitangent * (r * angle.sin());
+
+        Some(p - origin)
+    }
+
+    fn direction_pdf(&self, origin: Vec3f, dir: Vec3f) -> f64 {
+        let ray = Ray::new(origin, dir);
+        let mut rec = HitRecord::default();
+        if !self.hit(ray, 0.0001, f64::MAX, &mut rec) {
+            return 0.0;
+        }
+
+        // Converts the area density 1 / area to solid angle
+        let cosine = ray.direction().dot(&self.normal).abs();
+        rec.t * rec.t / (cosine * PI * self.radius * self.radius)
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::disk::Disk;
+    use crate::ray::Ray;
+    use crate::rng::Pcg32;
+    use crate::vec::Vec3f;
+    use crate::visible::{HitRecord, Visible};
+    use std::f64::consts::PI;
+
+    // Radius 2 on the z = -1 plane, facing the origin
+    fn disk() -> Disk {
+        Disk::new(Vec3f::new(0.0, 0.0, -1.0), Vec3f::new(0.0, 0.0, 1.0), 2.0)
+    }
+
+    #[test]
+    fn disk_hit() {
+        let disk = disk();
+        let mut rec = HitRecord::default();
+
+        let ray = Ray::new(Vec3f::new(1.0, 1.0, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(disk.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 1.0);
+        assert_eq!(rec.p, Vec3f::new(1.0, 1.0, -1.0));
+        assert_eq!(rec.normal, Vec3f::new(0.0, 0.0, 1.0));
+        assert!((rec.v - 2_f64.sqrt() / 2.0).abs() < 1e-12);
+
+        // The center and the rim
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(disk.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.v, 0.0);
+        let ray = Ray::new(Vec3f::new(0.0, 2.0, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(disk.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.v, 1.0);
+    }
+
+    #[test]
+    fn disk_miss() {
+        let disk = disk();
+        let mut rec = HitRecord::default();
+
+        // Just outside the rim
+        let ray = Ray::new(Vec3f::new(0.0, 2.001, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!disk.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Grazing along the plane itself
+        let ray = Ray::new(Vec3f::new(-5.0, 0.0, -1.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert!(!disk.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Pointing away
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(!disk.hit(ray, 0.0001, f64::MAX, &mut rec));
+    }
+
+    #[test]
+    fn disk_bounds_and_pdf() {
+        let b = disk().bounding_box().unwrap();
+        assert!(b.min().x() < -1.99 && b.max().y() > 1.99);
+        assert!(b.max().z() - b.min().z() < 0.001);
+
+        // Straight on from a distance of 1 the density is 1 / area
+        let disk = disk();
+        let origin = Vec3f::new(0.0, 0.0, 0.0);
+        let pdf = disk.direction_pdf(origin, Vec3f::new(0.0, 0.0, -1.0));
+        assert!((pdf - 1.0 / (4.0 * PI)).abs() < 1e-12);
+
+        let mut rng = Pcg32::new(0, 0);
+        for _ in 0..100 {
+            let dir = disk.sample_direction(origin, &mut rng).unwrap();
+            assert!(disk.direction_pdf(origin, dir) > 0.0);
+        }
+    }
+}
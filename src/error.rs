@@ -0,0 +1,62 @@
+use crate::scene::SceneError;
+use std::fmt;
+use std::io;
+
+// Everything that can go wrong loading, rendering or saving, so programs
+// using the crate can report it instead of crashing
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    // A scene file that can't be parsed or built
+    Scene(SceneError),
+    // An OBJ file or its materials that can't be loaded
+    Mesh(tobj::LoadError),
+    // Saving to a format that isn't one of IMAGE_FORMATS
+    UnknownFormat(String),
+    // Render settings that can't give an image, like a zero width
+    InvalidSettings(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Scene(e) => write!(f, "{}", e),
+            Error::Mesh(e) => write!(f, "could not load mesh: {}", e),
+            Error::UnknownFormat(message) | Error::InvalidSettings(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Scene(e) => Some(e),
+            Error::Mesh(e) => Some(e),
+            Error::UnknownFormat(_) | Error::InvalidSettings(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<SceneError> for Error {
+    fn from(e: SceneError) -> Error {
+        Error::Scene(e)
+    }
+}
+
+impl From<tobj::LoadError> for Error {
+    fn from(e: tobj::LoadError) -> Error {
+        Error::Mesh(e)
+    }
+}
@@ -0,0 +1,573 @@
+use crate::material::{facing_normal, uniform_sphere, BsdfSample};
+use crate::ray::Ray;
+use crate::sampler::SampleStream;
+use crate::vec::Vec3f;
+use crate::visible::{HitRecord, Visible};
+use crate::world::World;
+
+// Bounces after which paths are cut short, unless told otherwise
+pub const DEFAULT_MAX_DEPTH: usize = 50;
+
+// Names accepted by integrator_from_name()
+pub const INTEGRATOR_NAMES: [&str; 7] = [
+    "path",
+    "mis",
+    "normals",
+    "depth",
+    "albedo",
+    "hit_count",
+    "ao",
+];
+
+// Offset that keeps rays from hitting the surface they leave from
+const T_MIN: f64 = 0.0001;
+
+// Computes the light arriving at the camera along a ray
+pub trait Integrator: Send + Sync {
+    // One of INTEGRATOR_NAMES
+    fn name(&self) -> &'static str;
+
+    // Takes its random numbers from `stream`, so samplers can spread them
+    // out
+    fn radiance(&self, r: Ray, world: &World, stream: &mut SampleStream) -> Vec3f;
+
+    // Called once before rendering, for integrators that need to know
+    // something about the world, like its size
+    fn preprocess(&mut self, _world: &World) {}
+}
+
+pub fn integrator_from_name(name: &str, max_depth: usize) -> Option<Box<dyn Integrator>> {
+    let integrator: Box<dyn Integrator> = match name {
+        "path" => Box::new(PathTracer::new(max_depth)),
+        "mis" => Box::new(MisPathTracer::new(max_depth)),
+        "normals" => Box::new(Normals),
+        "depth" => Box::new(Depth::new()),
+        "albedo" => Box::new(Albedo),
+        "hit_count" => Box::new(HitCount),
+        "ao" => Box::new(AmbientOcclusion::new(16)),
+        _ => return None,
+    };
+
+    Some(integrator)
+}
+
+// Length of the diagonal of the world, or a guess for unbounded worlds
+fn world_size(world: &World) -> f64 {
+    match world.bounding_box() {
+        Some(b) => b.extent().length(),
+        None => 100.0,
+    }
+}
+
+// Follows scattered rays until they escape or hit a light by chance
+pub struct PathTracer {
+    max_depth: usize,
+}
+
+impl PathTracer {
+    pub fn new(max_depth: usize) -> PathTracer {
+        PathTracer { max_depth }
+    }
+
+    fn color(&self, r: Ray, world: &World, depth: usize, stream: &mut SampleStream) -> Vec3f {
+        let mut rec = HitRecord::default();
+
+        if world.hit(r, T_MIN, f64::MAX, &mut rec) {
+            let emitted = rec.material.emitted();
+            if depth >= self.max_depth {
+                return emitted;
+            }
+
+            stream.start_bounce(depth);
+            return match rec.material.sample(&r, &rec, stream) {
+                Some(s) => {
+                    emitted + s.attenuation * self.color(s.scattered, world, depth + 1, stream)
+                }
+                None => emitted,
+            };
+        }
+
+        world.sky().color(r.direction())
+    }
+}
+
+impl Integrator for PathTracer {
+    fn name(&self) -> &'static str {
+        "path"
+    }
+
+    fn radiance(&self, r: Ray, world: &World, stream: &mut SampleStream) -> Vec3f {
+        self.color(r, world, 0, stream)
+    }
+}
+
+// Weight for a sample taken with density `a` when another strategy could have
+// produced it with density `b`
+fn power_heuristic(a: f64, b: f64) -> f64 {
+    let a2 = a * a;
+    let b2 = b * b;
+    if a2 + b2 == 0.0 {
+        return 0.0;
+    }
+
+    a2 / (a2 + b2)
+}
+
+// Path tracing with next event estimation. Light reaching a diffuse surface is
+// counted twice, once through a shadow ray towards a sampled light and once if
+// the scattered ray happens to hit a light, so both get weighted by MIS.
+pub struct MisPathTracer {
+    max_depth: usize,
+}
+
+impl MisPathTracer {
+    pub fn new(max_depth: usize) -> MisPathTracer {
+        MisPathTracer { max_depth }
+    }
+
+    // Light arriving at `rec` from a shadow ray sampled towards the lights
+    fn direct_light(&self, r_in: &Ray, rec: &HitRecord, dir: Vec3f, world: &World) -> Vec3f {
+        let f = rec.material.eval(r_in, rec, dir);
+        if f.squared_length() == 0.0 {
+            return Vec3f::default();
+        }
+
+        let light_pdf = world.light_pdf(rec.p, dir);
+        if light_pdf <= 0.0 {
+            return Vec3f::default();
+        }
+
+        let shadow = Ray::at_time(rec.p, dir, r_in.time());
+        let mut light_rec = HitRecord::default();
+        if !world.hit(shadow, T_MIN, f64::MAX, &mut light_rec) {
+            return Vec3f::default();
+        }
+
+        let emitted = light_rec.material.emitted();
+        let weight = power_heuristic(light_pdf, rec.material.pdf(r_in, rec, dir));
+        f * emitted * (weight / light_pdf)
+    }
+}
+
+impl Integrator for MisPathTracer {
+    fn name(&self) -> &'static str {
+        "mis"
+    }
+
+    fn radiance(&self, r: Ray, world: &World, stream: &mut SampleStream) -> Vec3f {
+        let mut radiance = Vec3f::default();
+        let mut throughput = Vec3f::new(1.0, 1.0, 1.0);
+        let mut ray = r;
+        // Density the previous bounce picked `ray` with, None if it was
+        // specular or the camera so lights it hits count in full
+        let mut scatter_pdf: Option<f64> = None;
+
+        for depth in 0..=self.max_depth {
+            let mut rec = HitRecord::default();
+            if !world.hit(ray, T_MIN, f64::MAX, &mut rec) {
+                radiance = radiance + throughput * world.sky().color(ray.direction());
+                break;
+            }
+
+            let emitted = rec.material.emitted();
+            if emitted.squared_length() > 0.0 {
+                let weight = match scatter_pdf {
+                    Some(pdf) => {
+                        power_heuristic(pdf, world.light_pdf(ray.origin(), ray.direction()))
+                    }
+                    None => 1.0,
+                };
+                radiance = radiance + throughput * emitted * weight;
+            }
+
+            if depth == self.max_depth {
+                break;
+            }
+            stream.start_bounce(depth);
+            let sample = rec.material.sample(&ray, &rec, stream);
+
+            // Specular bounces have no pdf, and no use for light samples.
+            // Lights still count where the scattered ray was absorbed, like
+            // rough surfaces sending it below the horizon.
+            let specular = matches!(sample, Some(BsdfSample { pdf: None, .. }));
+            if !specular {
+                if let Some(dir) = world.sample_light(rec.p, stream.rng()) {
+                    radiance = radiance + throughput * self.direct_light(&ray, &rec, dir, world);
+                }
+            }
+
+            let sample = match sample {
+                Some(sample) => sample,
+                None => break,
+            };
+            scatter_pdf = sample.pdf;
+
+            throughput = throughput * sample.attenuation;
+            ray = sample.scattered;
+        }
+
+        radiance
+    }
+}
+
+// Surface normals mapped from [-1, 1] to [0, 1], black where nothing is hit
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn name(&self) -> &'static str {
+        "normals"
+    }
+
+    fn radiance(&self, r: Ray, world: &World, _stream: &mut SampleStream) -> Vec3f {
+        let mut rec = HitRecord::default();
+        if !world.hit(r, T_MIN, f64::MAX, &mut rec) {
+            return Vec3f::default();
+        }
+
+        (rec.normal + Vec3f::new(1.0, 1.0, 1.0)) * 0.5
+    }
+}
+
+// Distance to the first hit, white up close fading to black at the far end
+// of the world
+pub struct Depth {
+    max_distance: f64,
+}
+
+impl Depth {
+    pub fn new() -> Depth {
+        Depth { max_distance: 1.0 }
+    }
+}
+
+impl Integrator for Depth {
+    fn name(&self) -> &'static str {
+        "depth"
+    }
+
+    fn radiance(&self, r: Ray, world: &World, _stream: &mut SampleStream) -> Vec3f {
+        let mut rec = HitRecord::default();
+        if !world.hit(r, T_MIN, f64::MAX, &mut rec) {
+            return Vec3f::default();
+        }
+
+        let d = (1.0 - rec.t / self.max_distance).max(0.0);
+        Vec3f::new(d, d, d)
+    }
+
+    fn preprocess(&mut self, world: &World) {
+        self.max_distance = world_size(world);
+    }
+}
+
+// Color of the first surface hit, without any lighting
+pub struct Albedo;
+
+impl Integrator for Albedo {
+    fn name(&self) -> &'static str {
+        "albedo"
+    }
+
+    fn radiance(&self, r: Ray, world: &World, _stream: &mut SampleStream) -> Vec3f {
+        let mut rec = HitRecord::default();
+        if !world.hit(r, T_MIN, f64::MAX, &mut rec) {
+            return world.sky().color(r.direction());
+        }
+
+        rec.material.albedo(&rec)
+    }
+}
+
+// How many surfaces the ray goes through, one tenth brighter for each. Handy
+// for spotting hidden or duplicated geometry.
+pub struct HitCount;
+
+impl Integrator for HitCount {
+    fn name(&self) -> &'static str {
+        "hit_count"
+    }
+
+    fn radiance(&self, r: Ray, world: &World, _stream: &mut SampleStream) -> Vec3f {
+        let mut count = 0;
+        let mut t_min = T_MIN;
+        let mut rec = HitRecord::default();
+        while count < 10 && world.hit(r, t_min, f64::MAX, &mut rec) {
+            count += 1;
+            t_min = rec.t + T_MIN;
+        }
+
+        let c = count as f64 / 10.0;
+        Vec3f::new(c, c, c)
+    }
+}
+
+// Fraction of the hemisphere above the first hit that isn't blocked by
+// anything within a tenth of the size of the world
+pub struct AmbientOcclusion {
+    samples: usize,
+    distance: f64,
+}
+
+impl AmbientOcclusion {
+    pub fn new(samples: usize) -> AmbientOcclusion {
+        AmbientOcclusion {
+            samples,
+            distance: 1.0,
+        }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn name(&self) -> &'static str {
+        "ao"
+    }
+
+    fn radiance(&self, r: Ray, world: &World, stream: &mut SampleStream) -> Vec3f {
+        let mut rec = HitRecord::default();
+        if !world.hit(r, T_MIN, f64::MAX, &mut rec) {
+            return Vec3f::new(1.0, 1.0, 1.0);
+        }
+
+        let normal = facing_normal(&r, &rec);
+        let mut unoccluded = 0;
+        stream.start_bounce(0);
+        for _ in 0..self.samples {
+            // Cosine weighted, like a white Lambertian surface would see it
+            let dir = normal + uniform_sphere(stream.get_2d());
+            if dir.squared_length() < 1e-12 {
+                continue;
+            }
+
+            let mut occluder = HitRecord::default();
+            if !world.hit(
+                Ray::at_time(rec.p, dir, r.time()),
+                T_MIN,
+                self.distance,
+                &mut occluder,
+            ) {
+                unoccluded += 1;
+            }
+        }
+
+        let ao = unoccluded as f64 / self.samples as f64;
+        Vec3f::new(ao, ao, ao)
+    }
+
+    fn preprocess(&mut self, world: &World) {
+        self.distance = world_size(world) * 0.1;
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::integrator::{integrator_from_name, INTEGRATOR_NAMES};
+    use crate::material::Material;
+    use crate::quad::Quad;
+    use crate::ray::Ray;
+    use crate::sampler::{sampler_from_name, SampleStream, SAMPLER_NAMES};
+    use crate::sky::Sky;
+    use crate::sphere::Sphere;
+    use crate::texture::Texture;
+    use crate::vec::Vec3f;
+    use crate::visible::{HitRecord, Visible};
+    use crate::world::World;
+
+    // A grey floor under a small spherical lamp, in the dark. The floor
+    // right under the lamp reflects albedo * emit * (r / d)^2.
+    fn lamp_over_floor() -> World {
+        let mut world = World::default();
+        world.set_sky(Sky::Solid {
+            color: Vec3f::default(),
+        });
+
+        let mut floor = Quad::new(
+            Vec3f::new(-50.0, 0.0, -50.0),
+            Vec3f::new(0.0, 0.0, 100.0),
+            Vec3f::new(100.0, 0.0, 0.0),
+        );
+        floor.set_material(Material::Lambertian {
+            albedo: Texture::Solid(Vec3f::new(0.5, 0.5, 0.5)),
+        });
+        world.add(Box::new(floor));
+
+        let mut lamp = Sphere::new(Vec3f::new(0.0, 4.0, 0.0), 1.0, HitRecord::default());
+        lamp.set_material(Material::DiffuseLight {
+            emit: Vec3f::new(10.0, 10.0, 10.0),
+        });
+        world.add_light(Box::new(lamp));
+        world.build_acceleration();
+
+        world
+    }
+
+    // Looks at the floor right under the lamp
+    fn floor_ray() -> Ray {
+        Ray::new(Vec3f::new(3.0, 3.0, 0.0), Vec3f::new(-3.0, -3.0, 0.0))
+    }
+
+    // Mean and variance of the red channel
+    fn estimate(name: &str, world: &World, samples: usize) -> (f64, f64) {
+        estimate_with(name, "independent", world, samples)
+    }
+
+    // With the samples all from the same pixel
+    fn estimate_with(name: &str, sampler: &str, world: &World, samples: usize) -> (f64, f64) {
+        let mut integrator = integrator_from_name(name, 50).unwrap();
+        integrator.preprocess(world);
+        let sampler = sampler_from_name(sampler, samples).unwrap();
+
+        let values: Vec<f64> = (0..samples)
+            .map(|i| {
+                let mut stream = SampleStream::new(sampler.as_ref(), 0, 0, i);
+                integrator.radiance(floor_ray(), world, &mut stream).r()
+            })
+            .collect();
+        let mean = values.iter().sum::<f64>() / samples as f64;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / samples as f64;
+
+        (mean, variance)
+    }
+
+    #[test]
+    fn integrators_agree_with_analytic_result() {
+        let world = lamp_over_floor();
+        let expected = 0.5 * 10.0 / 16.0;
+
+        let (path_mean, path_variance) = estimate("path", &world, 40000);
+        let (mis_mean, mis_variance) = estimate("mis", &world, 4000);
+
+        assert!((path_mean - expected).abs() < 0.04, "path: {}", path_mean);
+        assert!((mis_mean - expected).abs() < 0.02, "mis: {}", mis_mean);
+        // Sampling the lamp directly is the whole point
+        assert!(mis_variance * 10.0 < path_variance);
+    }
+
+    #[test]
+    fn samplers_agree() {
+        let world = lamp_over_floor();
+        let expected = 0.5 * 10.0 / 16.0;
+
+        for sampler in SAMPLER_NAMES.iter() {
+            let (mean, _) = estimate_with("mis", sampler, &world, 4000);
+            assert!((mean - expected).abs() < 0.02, "{}: {}", sampler, mean);
+        }
+    }
+
+    // A sphere of `material` lit evenly from every direction, by a white
+    // sky or by the walls of a box of lights around it
+    fn furnace(material: &Material, walls: bool) -> World {
+        let mut world = World::default();
+        let mut sphere = Sphere::new(Vec3f::new(0.0, 1.0, 0.0), 1.0, HitRecord::default());
+        sphere.set_material(material.clone());
+        world.add(Box::new(sphere));
+
+        let white = Vec3f::new(1.0, 1.0, 1.0);
+        if walls {
+            world.set_sky(Sky::Solid {
+                color: Vec3f::default(),
+            });
+            for axis in 0..3 {
+                for side in [-10.0, 10.0].iter() {
+                    let mut a = Vec3f::new(-10.0, -10.0, -10.0);
+                    let mut b = Vec3f::new(10.0, 10.0, 10.0);
+                    a.set(axis, *side);
+                    b.set(axis, *side);
+                    let mut wall = Quad::axis_aligned(a, b).unwrap();
+                    wall.set_material(Material::DiffuseLight { emit: white });
+                    world.add_light(Box::new(wall));
+                }
+            }
+        } else {
+            world.set_sky(Sky::Solid { color: white });
+        }
+        world.build_acceleration();
+
+        world
+    }
+
+    #[test]
+    fn white_furnace() {
+        // A white sphere that doesn't absorb anything disappears, whichever
+        // way it scatters
+        let white = || Texture::Solid(Vec3f::new(1.0, 1.0, 1.0));
+        let lossless = [
+            Material::Lambertian { albedo: white() },
+            Material::Metal {
+                albedo: white(),
+                fuzz: 0.3,
+            },
+            Material::Dielectric { ri: 1.5 },
+            Material::Isotropic { albedo: white() },
+        ];
+        for material in lossless.iter() {
+            // Every path ends in the sky with nothing lost on the way
+            let world = furnace(material, false);
+            for name in ["path", "mis"].iter() {
+                let (mean, variance) = estimate(name, &world, 1000);
+                assert!((mean - 1.0).abs() < 1e-9, "{}: {}", name, mean);
+                assert!(variance < 1e-12);
+            }
+
+            // Light samples weighted with eval() and pdf() add up the same
+            let world = furnace(material, true);
+            let (mean, _) = estimate("mis", &world, 4000);
+            assert!((mean - 1.0).abs() < 0.02, "{}", mean);
+        }
+
+        // Single scattering microfacets lose some energy to light bouncing
+        // between them, but never make any. Sampling the lights has to find
+        // the same amount as following the scattered rays.
+        let rough = [0.1, 0.5, 1.0];
+        for (roughness, metallic) in rough.iter().flat_map(|r| [(*r, 0.0), (*r, 1.0)]) {
+            let material = Material::Microfacet {
+                albedo: white(),
+                roughness,
+                metallic,
+                ri: 1.5,
+            };
+            let (scattered, _) = estimate("path", &furnace(&material, false), 4000);
+            let (lit, _) = estimate("mis", &furnace(&material, true), 4000);
+
+            let label = format!("roughness {} metallic {}", roughness, metallic);
+            assert!(
+                scattered > 0.3 && scattered < 1.02,
+                "{}: {}",
+                label,
+                scattered
+            );
+            assert!(
+                (lit - scattered).abs() < 0.02,
+                "{}: {} {}",
+                label,
+                lit,
+                scattered
+            );
+        }
+    }
+
+    #[test]
+    fn debug_integrators() {
+        let world = lamp_over_floor();
+
+        assert_eq!(estimate("normals", &world, 1).0, 0.5);
+        assert_eq!(estimate("albedo", &world, 1).0, 0.5);
+        // Only the floor is in the way
+        assert_eq!(estimate("hit_count", &world, 1).0, 0.1);
+        // The lamp is the only thing above the floor
+        let (ao, _) = estimate("ao", &world, 100);
+        assert!(ao > 0.5 && ao <= 1.0, "ao: {}", ao);
+
+        let (depth, _) = estimate("depth", &world, 1);
+        assert!(depth > 0.9 && depth < 1.0, "depth: {}", depth);
+    }
+
+    #[test]
+    fn every_name_builds() {
+        for name in INTEGRATOR_NAMES.iter() {
+            let integrator = integrator_from_name(name, 10).unwrap();
+            assert_eq!(integrator.name(), *name);
+        }
+        assert!(integrator_from_name("whitted", 10).is_none());
+    }
+}
@@ -1,7 +1,30 @@
+mod aabb;
+mod bvh;
 mod camera;
+mod cuboid;
+mod cylinder;
+mod disk;
+mod error;
+mod integrator;
 mod material;
+mod medium;
+mod microfacet;
+mod obj;
+mod output;
+mod plane;
+mod quad;
 mod ray;
+mod render;
+mod rng;
+mod sampler;
+mod scene;
+mod sky;
 mod sphere;
+mod texture;
+mod tile;
+mod tonemap;
+mod transform;
+mod triangle;
 mod vec;
 mod visible;
 mod world;
@@ -9,16 +32,37 @@ mod world;
 extern crate indicatif;
 extern crate rand;
 
-use crate::material::Material;
-use crate::visible::{HitRecord, Visible};
-use camera::Camera;
-use indicatif::ProgressBar;
+pub use camera::Camera;
+pub use cuboid::Cuboid;
+pub use cylinder::{Cone, Cylinder};
+pub use disk::Disk;
+pub use error::{Error, Result};
+pub use integrator::{integrator_from_name, Integrator, DEFAULT_MAX_DEPTH, INTEGRATOR_NAMES};
+pub use material::{BsdfSample, Material};
+pub use medium::ConstantMedium;
+pub use output::{save_image, save_image_as, vec_from_hex, IMAGE_FORMATS};
+pub use plane::Plane;
+pub use quad::Quad;
 use rand::Rng;
-use rayon::prelude::*;
-use sphere::Sphere;
-use vec::Vec3f;
+pub use ray::Ray;
+pub use render::{AdaptiveSampling, Framebuffer, RenderJob, RenderSettings};
+pub use rng::Pcg32;
+pub use sampler::{
+    sampler_from_name, Halton, Independent, SampleStream, Sampler, Sobol, Stratified, SAMPLER_NAMES,
+};
+pub use scene::{load_scene, Scene, SceneError};
+pub use sky::Sky;
+pub use sphere::{MovingSphere, Sphere};
+use std::path::Path;
+pub use texture::{ImageTexture, Perlin, Texture};
+pub use tile::{Tile, TileOrder, DEFAULT_TILE_SIZE, TILE_ORDER_NAMES};
+pub use tonemap::{luminance, ToneMap, ToneMapping, TONE_MAP_NAMES};
+pub use transform::Transform;
+pub use triangle::{Triangle, TriangleMesh};
 use vec::Vec3i;
-use world::World;
+pub use vec::{Mat4, Vec3f};
+pub use visible::{HitRecord, Visible};
+pub use world::World;
 
 pub fn blank_screen(width: usize, height: usize) -> Vec<u32> {
     let mut buffer: Vec<u32> = vec![0; width * height];
@@ -49,20 +93,21 @@ pub fn gradient(width: usize, height: usize) -> Vec<u32> {
     buffer
 }
 
-pub fn generate_random_scene() -> World {
-    let mut rng = rand::thread_rng();
+// The same seed always gives the same scene
+pub fn generate_random_scene(seed: u64) -> World {
+    let mut rng = Pcg32::new(seed, 0);
     let mut world = World::default();
 
     let mut metal = Sphere::new(Vec3f::new(4.0, 1.0, 0.0), 1.0, HitRecord::default());
     metal.set_material(Material::Metal {
-        albedo: Vec3f::new(0.7, 0.6, 0.5),
+        albedo: Texture::Solid(Vec3f::new(0.7, 0.6, 0.5)),
         fuzz: 0.0,
     });
     let mut glass = Sphere::new(Vec3f::new(0.0, 1.0, 0.0), 1.0, HitRecord::default());
     glass.set_material(Material::Dielectric { ri: 1.5 });
     let mut lamb = Sphere::new(Vec3f::new(-4.0, 1.0, 0.0), 1.0, HitRecord::default());
     lamb.set_material(Material::Lambertian {
-        albedo: Vec3f::new(0.4, 0.2, 0.1),
+        albedo: Texture::Solid(Vec3f::new(0.4, 0.2, 0.1)),
     });
 
     world.add(Box::new(glass));
@@ -78,39 +123,44 @@ pub fn generate_random_scene() -> World {
                 f64::from(b) + rng.gen_range(0.0, 0.9),
             );
             if (center - Vec3f::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                let mut tmp = Sphere::new(center, 0.2, HitRecord::default());
                 if mat < 0.8 {
-                    // difuse
-                    tmp.set_material(Material::Lambertian {
-                        albedo: Vec3f::new(
+                    // Diffuse ones bounce while the shutter is open
+                    let center1 = center + Vec3f::new(0.0, rng.gen_range(0.0, 0.5), 0.0);
+                    let mut sphere =
+                        MovingSphere::new(center, center1, 0.0, 1.0, 0.2, HitRecord::default());
+                    sphere.set_material(Material::Lambertian {
+                        albedo: Texture::Solid(Vec3f::new(
                             rng.gen_range(0.0, 1.0),
                             rng.gen_range(0.0, 1.0),
                             rng.gen_range(0.0, 1.0),
-                        ),
+                        )),
                     });
+                    world.add(Box::new(sphere));
                 } else if mat < 0.95 {
                     // metal
-                    tmp.set_material(Material::Metal {
-                        albedo: Vec3f::new(
+                    let mut sphere = Sphere::new(center, 0.2, HitRecord::default());
+                    sphere.set_material(Material::Metal {
+                        albedo: Texture::Solid(Vec3f::new(
                             0.5 * (1.0 + rng.gen_range(0.0, 1.0)),
                             0.5 * (1.0 + rng.gen_range(0.0, 1.0)),
                             0.5 * (1.0 + rng.gen_range(0.0, 1.0)),
-                        ),
+                        )),
                         fuzz: rng.gen_range(0.0, 0.5),
                     });
+                    world.add(Box::new(sphere));
                 } else {
                     // glass
-                    tmp.set_material(Material::Dielectric { ri: 1.5 });
+                    let mut sphere = Sphere::new(center, 0.2, HitRecord::default());
+                    sphere.set_material(Material::Dielectric { ri: 1.5 });
+                    world.add(Box::new(sphere));
                 }
-
-                world.add(Box::new(tmp));
             }
         }
     }
 
-    let mut ground = Sphere::new(Vec3f::new(0.0, -1000.0, 0.0), 1000.0, HitRecord::default());
+    let mut ground = Plane::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 1.0, 0.0));
     ground.set_material(Material::Lambertian {
-        albedo: Vec3f::new(0.5, 0.5, 0.5),
+        albedo: Texture::checker(Vec3f::new(0.2, 0.3, 0.1), Vec3f::new(0.9, 0.9, 0.9), 1.0),
     });
     world.add(Box::new(ground));
 
@@ -122,22 +172,22 @@ pub fn generate_scene() -> World {
 
     let mut lamb_a = Sphere::new(Vec3f::new(0.3, -0.1, -1.0), 0.4, HitRecord::default());
     lamb_a.set_material(Material::Lambertian {
-        albedo: Vec3f::new(0.8, 0.3, 0.3),
+        albedo: Texture::Solid(Vec3f::new(0.8, 0.3, 0.3)),
     });
     let mut glass = Sphere::new(Vec3f::new(-0.4, 0.0, -1.0), 0.3, HitRecord::default());
     glass.set_material(Material::Dielectric { ri: 1.5 });
     let mut metal = Sphere::new(Vec3f::new(1.2, 0.0, -1.0), 0.3, HitRecord::default());
     metal.set_material(Material::Metal {
-        albedo: Vec3f::new(0.8, 0.6, 0.4),
+        albedo: Texture::Solid(Vec3f::new(0.8, 0.6, 0.4)),
         fuzz: 0.1,
     });
     let mut lamb_b = Sphere::new(Vec3f::new(-3.5, 0.2, -3.0), 0.8, HitRecord::default());
     lamb_b.set_material(Material::Lambertian {
-        albedo: Vec3f::new(0.1, 0.2, 0.5),
+        albedo: Texture::Solid(Vec3f::new(0.1, 0.2, 0.5)),
     });
     let mut ground = Sphere::new(Vec3f::new(0.0, -100.5, -1.0), 100.0, HitRecord::default());
     ground.set_material(Material::Lambertian {
-        albedo: Vec3f::new(0.5, 0.8, 0.2),
+        albedo: Texture::Solid(Vec3f::new(0.5, 0.8, 0.2)),
     });
 
     world.add(Box::new(lamb_a));
@@ -149,11 +199,34 @@ pub fn generate_scene() -> World {
     world
 }
 
-pub fn render(width: usize, height: usize, samples: usize) -> Vec<u32> {
-    println!("Running with {} samples per pixel.", samples);
+// Every object in an OBJ file sitting on a grey ground plane
+pub fn generate_mesh_scene(obj_path: &Path) -> Result<World> {
+    let mut world = World::default();
 
-    let mut buffer: Vec<u32> = vec![0; width * height];
+    let mut floor = f64::MAX;
+    for mesh in obj::load_obj(obj_path)? {
+        if let Some(b) = mesh.bounding_box() {
+            floor = floor.min(b.min().y());
+        }
+        world.add(Box::new(mesh));
+    }
+    if floor == f64::MAX {
+        floor = 0.0;
+    }
 
+    let mut ground = Plane::new(Vec3f::new(0.0, floor, 0.0), Vec3f::new(0.0, 1.0, 0.0));
+    ground.set_material(Material::Lambertian {
+        albedo: Texture::Solid(Vec3f::new(0.5, 0.5, 0.5)),
+    });
+    world.add(Box::new(ground));
+
+    Ok(world)
+}
+
+// The random spheres scene from the cover of "Ray Tracing in One Weekend",
+// ready to render with one of INTEGRATOR_NAMES. The seed in the settings also
+// places the spheres.
+pub fn random_scene_job(settings: RenderSettings, integrator: &str) -> Result<RenderJob> {
     let look_from = Vec3f::new(12.0, 1.0, 3.0);
     let look_at = Vec3f::new(-4.0, 0.2, -1.0);
     let distance_to_focus = 10.0;
@@ -165,57 +238,29 @@ pub fn render(width: usize, height: usize, samples: usize) -> Vec<u32> {
         look_at,
         vup,
         20.0,
-        width as f64 / height as f64,
+        settings.width as f64 / settings.height as f64,
         aperture,
         distance_to_focus,
-    );
-
-    let world = generate_random_scene();
-
-    let pb = ProgressBar::new(height as u64);
-    pb.set_style(
-        indicatif::ProgressStyle::default_bar()
-            .template("[{elapsed_precise}] [{bar:70.cyan/blue}]  {percent}%  ({eta})")
-            .progress_chars("#-"),
-    );
+    )
+    .with_shutter(0.0, 1.0);
 
-    buffer
-        .par_chunks_mut(width)
-        .enumerate()
-        .for_each(|(y, row)| {
-            let mut rng = rand::thread_rng();
-            for (x, pixel) in row.iter_mut().enumerate() {
-                let mut color = Vec3f::default();
-                for _s in 0..samples {
-                    let u = (x as f64 + rng.gen_range(0.0, 1.0)) / width as f64;
-                    let v = ((height - y) as f64 + rng.gen_range(0.0, 1.0)) / height as f64;
-
-                    let p = camera::color(camera.get_ray(u, v), &world, 0);
-                    color = color + p;
-                }
-                color = color / samples as f64;
-                color.set_r(color.r().sqrt());
-                color.set_g(color.g().sqrt());
-                color.set_b(color.b().sqrt());
-                let color = Vec3i::new_from_f64(color);
-                *pixel = color.to_hex();
-            }
+    let world = generate_random_scene(settings.seed);
+    RenderJob::new(world, camera, settings)?.with_integrator(integrator)
+}
 
-            pb.inc(1);
-        });
-    pb.finish_with_message("Render done");
+// Renders the random spheres scene. Use a RenderJob to render anything else.
+pub fn render(width: usize, height: usize, samples: usize, integrator: &str) -> Result<Vec<u32>> {
+    let settings = RenderSettings {
+        width,
+        height,
+        samples,
+        ..Default::default()
+    };
 
-    buffer
+    Ok(random_scene_job(settings, integrator)?.render())
 }
 
-pub fn vec_from_hex(orig: Vec<u32>) -> Vec<u8> {
-    let orig_len = orig.len();
-    let mut out = vec![0 as u8; orig_len * 3];
-    for c in 0..orig_len {
-        out[c * 3] = (orig[c] >> 16) as u8;
-        out[c * 3 + 1] = ((orig[c] & 0xff00) >> 8) as u8;
-        out[c * 3 + 2] = (orig[c] & 0xff) as u8;
-    }
-
-    out
+// Renders a scene loaded from a file, with the settings it describes
+pub fn render_scene(scene: Scene) -> Result<Vec<u32>> {
+    Ok(scene.into_job()?.render())
 }
@@ -1,13 +1,41 @@
+use crate::microfacet::{Ggx, Principled};
 use crate::ray::Ray;
+use crate::sampler::SampleStream;
+use crate::texture::Texture;
 use crate::vec::Vec3f;
 use crate::visible::HitRecord;
-use rand::Rng;
+use std::f64::consts::PI;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub enum Material {
-    Lambertian { albedo: Vec3f },
-    Metal { albedo: Vec3f, fuzz: f64 },
-    Dielectric { ri: f64 },
+    Lambertian {
+        albedo: Texture,
+    },
+    Metal {
+        albedo: Texture,
+        fuzz: f64,
+    },
+    Dielectric {
+        ri: f64,
+    },
+    // Emits light on both sides and doesn't reflect any
+    DiffuseLight {
+        emit: Vec3f,
+    },
+    // Scatters the same amount in every direction, for the inside of
+    // participating media
+    Isotropic {
+        albedo: Texture,
+    },
+    // Rough metal or plastic-like surface off GGX microfacets. `metallic`
+    // goes from a diffuse base under a clear coat with index `ri` (0) to a
+    // metal tinted by the albedo (1), `roughness` from mirror to matte.
+    Microfacet {
+        albedo: Texture,
+        roughness: f64,
+        metallic: f64,
+        ri: f64,
+    },
 }
 
 fn reflect(v: Vec3f, n: Vec3f) -> Vec3f {
@@ -25,20 +53,45 @@ fn refract(v: Vec3f, n: Vec3f, ni_over_nt: f64) -> Option<Vec3f> {
     }
 }
 
-fn random_point_in_unit_sphere() -> Vec3f {
-    let mut rng = rand::thread_rng();
-    let mut p: Vec3f;
+// Point on the unit sphere for a point in the unit square, keeping areas in
+// proportion
+pub fn uniform_sphere((u, v): (f64, f64)) -> Vec3f {
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
 
-    while {
-        let rnd_x = rng.gen_range(-1.0, 1.0);
-        let rnd_y = rng.gen_range(-1.0, 1.0);
-        let rnd_z = rng.gen_range(-1.0, 1.0);
-        p = Vec3f::new(rnd_x, rnd_y, rnd_z);
+    Vec3f::new(r * phi.cos(), r * phi.sin(), z)
+}
 
-        p.squared_length() < 1.0
-    } {}
+// Uniform in the volume of the unit ball: a direction, and a distance that
+// puts as many points in every shell as its volume calls for
+fn point_in_unit_ball(stream: &mut SampleStream) -> Vec3f {
+    uniform_sphere(stream.get_2d()) * stream.get_1d().cbrt()
+}
 
-    p
+// Open surfaces like triangles can be hit from either side, opaque materials
+// scatter off the side the ray came from
+pub fn facing_normal(r_in: &Ray, rec: &HitRecord) -> Vec3f {
+    if r_in.direction().dot(&rec.normal) > 0.0 {
+        rec.normal * -1.0
+    } else {
+        rec.normal
+    }
+}
+
+fn principled(
+    rec: &HitRecord,
+    albedo: &Texture,
+    roughness: f64,
+    metallic: f64,
+    ri: f64,
+) -> Principled {
+    Principled {
+        base: albedo.value(rec.u, rec.v, rec.p),
+        ggx: Ggx::new(roughness),
+        metallic,
+        ri,
+    }
 }
 
 fn shlick(cosine: f64, ref_idx: f64) -> f64 {
@@ -47,36 +100,69 @@ fn shlick(cosine: f64, ref_idx: f64) -> f64 {
     r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
 }
 
+// A direction picked by Material::sample()
+#[derive(Copy, Clone)]
+pub struct BsdfSample {
+    pub scattered: Ray,
+    // What the light coming back along `scattered` gets multiplied by. When
+    // there is a pdf it's eval() / pdf.
+    pub attenuation: Vec3f,
+    // Density over solid angle the direction was picked with. None when it
+    // can't be evaluated: for specular materials that could only have picked
+    // this one, and for Metal, whose fuzz jitters the mirror direction by a
+    // point in a ball without a closed form density. Integrators treat both
+    // like a mirror bounce and never light sample them.
+    pub pdf: Option<f64>,
+}
+
 impl Material {
-    pub fn scatter(
+    // Picks a direction to continue a path hitting `rec` along r_in. None if
+    // the light is absorbed instead.
+    pub fn sample(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Vec3f,
-        scattered: &mut Ray,
-    ) -> bool {
+        stream: &mut SampleStream,
+    ) -> Option<BsdfSample> {
         match *self {
             Material::Metal {
                 ref albedo,
                 ref fuzz,
             } => {
-                let reflected = reflect(r_in.direction(), rec.normal);
-                *scattered = Ray::new(rec.p, reflected + random_point_in_unit_sphere() * (*fuzz));
-                *attenuation = *albedo;
+                let normal = facing_normal(r_in, rec);
+                let reflected = reflect(r_in.direction(), normal);
+                let scattered = Ray::at_time(
+                    rec.p,
+                    reflected + point_in_unit_ball(stream) * (*fuzz),
+                    r_in.time(),
+                );
+                if scattered.direction().dot(&normal) <= 0.0 {
+                    return None;
+                }
 
-                scattered.direction().dot(&rec.normal) > 0.0
+                Some(BsdfSample {
+                    scattered,
+                    attenuation: albedo.value(rec.u, rec.v, rec.p),
+                    pdf: None,
+                })
             }
             Material::Lambertian { ref albedo } => {
-                let target = rec.p + rec.normal + random_point_in_unit_sphere();
-                *scattered = Ray::new(rec.p, target - rec.p);
-                *attenuation = *albedo;
+                // Offsetting the normal by a point on the unit sphere gives
+                // a cosine weighted direction, matching pdf()
+                let normal = facing_normal(r_in, rec);
+                let mut direction = normal + uniform_sphere(stream.get_2d());
+                if direction.squared_length() < 1e-12 {
+                    direction = normal;
+                }
+                let scattered = Ray::at_time(rec.p, direction, r_in.time());
 
-                true
+                Some(BsdfSample {
+                    scattered,
+                    attenuation: albedo.value(rec.u, rec.v, rec.p),
+                    pdf: Some(self.pdf(r_in, rec, scattered.direction())),
+                })
             }
             Material::Dielectric { ref ri } => {
-                let mut rng = rand::thread_rng();
-                *attenuation = Vec3f::new(1.0, 1.0, 1.0);
-
                 let reflected = reflect(r_in.direction(), rec.normal);
                 let outward_normal: Vec3f;
                 let ni_over_nt: f64;
@@ -90,7 +176,7 @@ impl Material {
                 } else {
                     outward_normal = rec.normal;
                     ni_over_nt = 1.0 / *ri;
-                    cosine = (r_in.direction().dot(&rec.normal) * -1.0) / r_in.direction().length();
+                    cosine = -r_in.direction().dot(&rec.normal) / r_in.direction().length();
                 }
 
                 let refracted = refract(r_in.direction(), outward_normal, ni_over_nt);
@@ -100,22 +186,244 @@ impl Material {
                     reflect_prob = 1.0;
                 }
 
-                if rng.gen_range(0.0, 1.0) < reflect_prob {
-                    *scattered = Ray::new(rec.p, reflected);
+                let direction = if stream.get_1d() < reflect_prob {
+                    reflected
                 } else {
-                    *scattered = Ray::new(rec.p, refracted.unwrap());
+                    refracted.unwrap()
+                };
+
+                Some(BsdfSample {
+                    scattered: Ray::at_time(rec.p, direction, r_in.time()),
+                    attenuation: Vec3f::new(1.0, 1.0, 1.0),
+                    pdf: None,
+                })
+            }
+            Material::Isotropic { ref albedo } => Some(BsdfSample {
+                scattered: Ray::at_time(rec.p, uniform_sphere(stream.get_2d()), r_in.time()),
+                attenuation: albedo.value(rec.u, rec.v, rec.p),
+                pdf: Some(1.0 / (4.0 * PI)),
+            }),
+            Material::Microfacet {
+                ref albedo,
+                roughness,
+                metallic,
+                ri,
+            } => {
+                let normal = facing_normal(r_in, rec);
+                let wo = r_in.direction() * -1.0;
+                let surface = principled(rec, albedo, roughness, metallic, ri);
+                let wi = surface.sample(normal, wo, stream)?;
+
+                // Weighted by the pdf of both lobes, so it agrees with
+                // eval() and pdf() when lights are sampled too
+                let pdf = surface.pdf(normal, wo, wi);
+                if pdf <= 0.0 {
+                    return None;
                 }
 
-                true
+                Some(BsdfSample {
+                    scattered: Ray::at_time(rec.p, wi, r_in.time()),
+                    attenuation: surface.eval(normal, wo, wi) / pdf,
+                    pdf: Some(pdf),
+                })
+            }
+            Material::DiffuseLight { .. } => None,
+        }
+    }
+
+    // Reflected fraction of the light arriving from `dir` towards where r_in
+    // came from, including the cosine term. Zero for materials sample() gives
+    // no pdf for, fuzzy Metal included.
+    pub fn eval(&self, r_in: &Ray, rec: &HitRecord, dir: Vec3f) -> Vec3f {
+        match *self {
+            Material::Lambertian { ref albedo } => {
+                albedo.value(rec.u, rec.v, rec.p) * self.pdf(r_in, rec, dir)
             }
+            // No cosine term inside a medium
+            Material::Isotropic { ref albedo } => {
+                albedo.value(rec.u, rec.v, rec.p) * self.pdf(r_in, rec, dir)
+            }
+            Material::Microfacet {
+                ref albedo,
+                roughness,
+                metallic,
+                ri,
+            } => principled(rec, albedo, roughness, metallic, ri).eval(
+                facing_normal(r_in, rec),
+                r_in.direction() * -1.0,
+                dir.make_unit_vector(),
+            ),
+            _ => Vec3f::default(),
+        }
+    }
+
+    // Probability density, over solid angle, of sample() picking `dir`
+    pub fn pdf(&self, r_in: &Ray, rec: &HitRecord, dir: Vec3f) -> f64 {
+        match *self {
+            Material::Lambertian { .. } => {
+                let cosine = facing_normal(r_in, rec).dot(&dir.make_unit_vector());
+                cosine.max(0.0) / PI
+            }
+            Material::Isotropic { .. } => 1.0 / (4.0 * PI),
+            Material::Microfacet {
+                ref albedo,
+                roughness,
+                metallic,
+                ri,
+            } => principled(rec, albedo, roughness, metallic, ri).pdf(
+                facing_normal(r_in, rec),
+                r_in.direction() * -1.0,
+                dir.make_unit_vector(),
+            ),
+            _ => 0.0,
+        }
+    }
+
+    // Base color of the surface at the hit, ignoring how it scatters light
+    pub fn albedo(&self, rec: &HitRecord) -> Vec3f {
+        match *self {
+            Material::Lambertian { ref albedo }
+            | Material::Metal { ref albedo, .. }
+            | Material::Isotropic { ref albedo }
+            | Material::Microfacet { ref albedo, .. } => albedo.value(rec.u, rec.v, rec.p),
+            Material::Dielectric { .. } => Vec3f::new(1.0, 1.0, 1.0),
+            Material::DiffuseLight { emit } => emit,
+        }
+    }
+
+    // Light given off by the surface itself, black for everything but lights
+    pub fn emitted(&self) -> Vec3f {
+        match *self {
+            Material::DiffuseLight { emit } => emit,
+            _ => Vec3f::default(),
         }
     }
 }
 
+// What objects are made of until they're given a material
+pub(crate) static DEFAULT_MATERIAL: Material = Material::Lambertian {
+    albedo: Texture::Solid(Vec3f::new(0.8, 0.8, 0.8)),
+};
+
 impl Default for Material {
     fn default() -> Material {
-        Material::Lambertian {
-            albedo: Vec3f::new(0.8, 0.8, 0.8),
+        DEFAULT_MATERIAL.clone()
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::sampler::{Independent, SampleStream};
+    use crate::texture::Texture;
+    use crate::vec::Vec3f;
+    use crate::visible::HitRecord;
+
+    fn white() -> Texture {
+        Texture::Solid(Vec3f::new(1.0, 1.0, 1.0))
+    }
+
+    // Hit at the origin of a surface facing up, from above at an angle
+    fn hit() -> (Ray, HitRecord<'static>) {
+        let rec = HitRecord {
+            normal: Vec3f::new(0.0, 1.0, 0.0),
+            ..HitRecord::default()
+        };
+        let ray = Ray::new(Vec3f::new(-1.0, 2.0, 0.5), Vec3f::new(1.0, -2.0, -0.5));
+
+        (ray, rec)
+    }
+
+    fn microfacet(roughness: f64, metallic: f64) -> Material {
+        Material::Microfacet {
+            albedo: white(),
+            roughness,
+            metallic,
+            ri: 1.5,
+        }
+    }
+
+    #[test]
+    fn sample_agrees_with_eval_and_pdf() {
+        let (ray, rec) = hit();
+        let mut stream = SampleStream::new(&Independent, 0, 0, 0);
+        let materials = [
+            Material::Lambertian { albedo: white() },
+            Material::Isotropic { albedo: white() },
+            microfacet(0.2, 1.0),
+            microfacet(0.5, 0.0),
+            microfacet(0.8, 0.5),
+        ];
+
+        for m in materials.iter() {
+            for _ in 0..1000 {
+                let s = match m.sample(&ray, &rec, &mut stream) {
+                    Some(s) => s,
+                    None => continue,
+                };
+                let dir = s.scattered.direction();
+                let pdf = s.pdf.unwrap();
+                assert!((pdf - m.pdf(&ray, &rec, dir)).abs() < 1e-9 * pdf.max(1.0));
+
+                let expected = m.eval(&ray, &rec, dir) / pdf;
+                assert!((s.attenuation - expected).length() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn white_furnace() {
+        // Averaged over many samples, a white material can't reflect more
+        // light than it gets, and the lossless ones reflect all of it
+        let (ray, rec) = hit();
+        let mut stream = SampleStream::new(&Independent, 1, 0, 0);
+        let mut reflected = |m: &Material| {
+            let n = 20_000;
+            let sum: f64 = (0..n)
+                .filter_map(|_| m.sample(&ray, &rec, &mut stream))
+                .map(|s| s.attenuation.x())
+                .sum();
+            sum / n as f64
+        };
+
+        let lossless = [
+            Material::Lambertian { albedo: white() },
+            Material::Metal {
+                albedo: white(),
+                fuzz: 0.0,
+            },
+            Material::Dielectric { ri: 1.5 },
+            Material::Isotropic { albedo: white() },
+        ];
+        for m in lossless.iter() {
+            assert!((reflected(m) - 1.0).abs() < 1e-9);
+        }
+
+        // Microfacets lose light to shadowing, rougher ones more. Matte
+        // metals only keep about a third at this angle.
+        let metals: Vec<f64> = [0.1, 0.5, 1.0]
+            .iter()
+            .map(|r| reflected(&microfacet(*r, 1.0)))
+            .collect();
+        assert!(metals[0] <= 1.01 && metals[0] > 0.95, "{:?}", metals);
+        assert!(
+            metals[1] < metals[0] && metals[2] < metals[1],
+            "{:?}",
+            metals
+        );
+        assert!(metals[2] > 0.3, "{:?}", metals);
+
+        for m in [microfacet(0.3, 0.0), microfacet(0.8, 0.5)].iter() {
+            let r = reflected(m);
+            assert!(r <= 1.01 && r > 0.5, "{}", r);
+        }
+
+        assert!(Material::DiffuseLight {
+            emit: Vec3f::new(1.0, 1.0, 1.0)
         }
+        .sample(&ray, &rec, &mut stream)
+        .is_none());
     }
 }
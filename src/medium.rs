@@ -0,0 +1,165 @@
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rng::hash_unit;
+use crate::texture::Texture;
+use crate::vec::Vec3f;
+use crate::visible::{HitRecord, Visible};
+
+// Fog, smoke or anything else of the same density throughout, filling a
+// closed boundary. Rays going through it scatter at exponentially
+// distributed distances, off an Isotropic material unless set otherwise.
+//
+// Visible::hit() gets no random generator, so the distance comes from a
+// hash of the ray instead. That keeps renders deterministic, and every
+// sample's rays are different anyway, but the sampler picked with
+// --sampler has no say in it (see TODO).
+pub struct ConstantMedium {
+    boundary: Box<dyn Visible>,
+    neg_inv_density: f64,
+    phase: Material,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Box<dyn Visible>, density: f64) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase: Material::Isotropic {
+                albedo: Texture::Solid(Vec3f::new(1.0, 1.0, 1.0)),
+            },
+        }
+    }
+}
+
+fn ray_bits(ray: &Ray) -> [u64; 7] {
+    let (o, d) = (ray.origin(), ray.direction());
+    [
+        o.x().to_bits(),
+        o.y().to_bits(),
+        o.z().to_bits(),
+        d.x().to_bits(),
+        d.y().to_bits(),
+        d.z().to_bits(),
+        ray.time().to_bits(),
+    ]
+}
+
+impl Visible for ConstantMedium {
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        // Where the ray enters and leaves the boundary, even if it starts
+        // inside
+        let mut enter = HitRecord::default();
+        if !self
+            .boundary
+            .hit(ray, f64::NEG_INFINITY, f64::INFINITY, &mut enter)
+        {
+            return false;
+        }
+        let mut leave = HitRecord::default();
+        if !self
+            .boundary
+            .hit(ray, enter.t + 0.0001, f64::INFINITY, &mut leave)
+        {
+            return false;
+        }
+
+        let t_enter = enter.t.max(t_min).max(0.0);
+        let t_leave = leave.t.min(t_max);
+        if t_enter >= t_leave {
+            return false;
+        }
+
+        // 1 - u so the logarithm never sees zero
+        let u = 1.0 - hash_unit(&ray_bits(&ray));
+        let distance = self.neg_inv_density * u.ln();
+        if distance > t_leave - t_enter {
+            return false;
+        }
+
+        rec.t = t_enter + distance;
+        rec.p = ray.point_at(rec.t);
+        // Meaningless inside a volume, but has to be something
+        rec.normal = Vec3f::new(1.0, 0.0, 0.0);
+        rec.u = 0.0;
+        rec.v = 0.0;
+        rec.material = &self.phase;
+
+        true
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.phase = m;
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::material::Material;
+    use crate::medium::ConstantMedium;
+    use crate::ray::Ray;
+    use crate::rng::Pcg32;
+    use crate::sphere::Sphere;
+    use crate::vec::Vec3f;
+    use crate::visible::{HitRecord, Visible};
+    use rand::Rng;
+
+    fn fog(density: f64) -> ConstantMedium {
+        let boundary = Sphere::new(Vec3f::default(), 1.0, HitRecord::default());
+        ConstantMedium::new(Box::new(boundary), density)
+    }
+
+    #[test]
+    fn scatters_inside_the_boundary() {
+        let fog = fog(2.0);
+        let mut rng = Pcg32::new(0, 0);
+        let mut rec = HitRecord::default();
+
+        let mut hits = 0;
+        for _ in 0..10_000 {
+            // Through the middle, so 2 units of fog
+            let origin = Vec3f::new(rng.gen_range(-1e-3, 1e-3), 0.0, 5.0);
+            let ray = Ray::new(origin, Vec3f::new(0.0, 0.0, -1.0));
+            if fog.hit(ray, 0.0001, f64::MAX, &mut rec) {
+                hits += 1;
+                assert!(rec.t >= 4.0 && rec.t <= 6.0);
+                assert!(matches!(rec.material, Material::Isotropic { .. }));
+            }
+        }
+
+        // The chance of getting through is exp(-density * length)
+        let expected = 10_000.0 * (1.0 - (-4.0_f64).exp());
+        assert!((f64::from(hits) - expected).abs() < 100.0, "{}", hits);
+    }
+
+    #[test]
+    fn starting_inside_and_missing() {
+        let fog = fog(1000.0);
+        let mut rec = HitRecord::default();
+
+        // From the center almost immediately
+        let ray = Ray::new(Vec3f::default(), Vec3f::new(1.0, 0.0, 0.0));
+        assert!(fog.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!(rec.t < 0.1);
+
+        // Beside the boundary, pointing away and stopping before it
+        let ray = Ray::new(Vec3f::new(0.0, 2.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!fog.hit(ray, 0.0001, f64::MAX, &mut rec));
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 5.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(!fog.hit(ray, 0.0001, f64::MAX, &mut rec));
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!fog.hit(ray, 0.0001, 3.9, &mut rec));
+
+        // The same ray always scatters at the same place
+        let ray = Ray::new(Vec3f::new(0.1, 0.2, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        let mut again = HitRecord::default();
+        assert!(fog.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!(fog.hit(ray, 0.0001, f64::MAX, &mut again));
+        assert_eq!(rec.t, again.t);
+    }
+}
@@ -0,0 +1,304 @@
+use crate::sampler::SampleStream;
+use crate::vec::Vec3f;
+use std::f64::consts::PI;
+
+// Below this the distribution is too close to a mirror to be sampled or
+// evaluated reliably
+const MIN_ALPHA: f64 = 1e-3;
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals with Smith
+// shadowing, as in Walter et al. "Microfacet Models for Refraction through
+// Rough Surfaces". Cosines are against the macro surface normal.
+#[derive(Copy, Clone, Debug)]
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    // Perceptual roughness in [0, 1], squared like most renderers do
+    pub fn new(roughness: f64) -> Ggx {
+        let r = roughness.clamp(0.0, 1.0);
+        Ggx {
+            alpha: (r * r).max(MIN_ALPHA),
+        }
+    }
+
+    // Density of microfacets facing the half vector, over solid angle
+    pub fn d(&self, cos_h: f64) -> f64 {
+        if cos_h <= 0.0 {
+            return 0.0;
+        }
+
+        let a2 = self.alpha * self.alpha;
+        let k = cos_h * cos_h * (a2 - 1.0) + 1.0;
+        a2 / (PI * k * k)
+    }
+
+    // Fraction of the surface seen from a direction that isn't hidden by
+    // other microfacets
+    pub fn g1(&self, cos: f64) -> f64 {
+        if cos <= 0.0 {
+            return 0.0;
+        }
+
+        let a2 = self.alpha * self.alpha;
+        2.0 * cos / (cos + (a2 + (1.0 - a2) * cos * cos).sqrt())
+    }
+
+    pub fn g(&self, cos_o: f64, cos_i: f64) -> f64 {
+        self.g1(cos_o) * self.g1(cos_i)
+    }
+
+    // Half vector picked with density d(h) * cos_h, around `normal`, for a
+    // point in the unit square
+    pub fn sample_half(&self, normal: Vec3f, (u1, u2): (f64, f64)) -> Vec3f {
+        let tan2 = self.alpha * self.alpha * u1 / (1.0 - u1);
+        let cos_theta = 1.0 / (1.0 + tan2).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+
+        let (t, b) = normal.orthonormal_basis();
+        t * (phi.cos() * sin_theta) + b * (phi.sin() * sin_theta) + normal * cos_theta
+    }
+
+    // Density, over solid angle, of reflecting `wo` into `wi` off a half
+    // vector from sample_half()
+    pub fn reflection_pdf(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> f64 {
+        let h = (wo + wi).make_unit_vector();
+        let wo_h = wo.dot(&h);
+        if wo_h <= 0.0 {
+            return 0.0;
+        }
+
+        self.d(normal.dot(&h)) * normal.dot(&h) / (4.0 * wo_h)
+    }
+}
+
+// A surface that is either a metal or a diffuse base under a clear coat of
+// dielectric, or a blend of both for `metallic` in between. Both reflect off
+// the same GGX microfacets.
+pub struct Principled {
+    pub base: Vec3f,
+    pub ggx: Ggx,
+    pub metallic: f64,
+    pub ri: f64,
+}
+
+impl Principled {
+    // How often the glossy lobe is sampled instead of the diffuse one
+    fn specular_weight(&self) -> f64 {
+        0.5 + 0.5 * self.metallic.clamp(0.0, 1.0)
+    }
+
+    // Light arriving from `wi` reflected towards `wo`, including the cosine
+    // term. Directions point away from the surface.
+    pub fn eval(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> Vec3f {
+        let (cos_o, cos_i) = (normal.dot(&wo), normal.dot(&wi));
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return Vec3f::default();
+        }
+
+        let h = (wo + wi).make_unit_vector();
+        let wi_h = wi.dot(&h);
+        let metallic = self.metallic.clamp(0.0, 1.0);
+
+        // D G / (4 cos_o cos_i), times the cosine of wi
+        let glossy = self.ggx.d(normal.dot(&h)) * self.ggx.g(cos_o, cos_i) / (4.0 * cos_o);
+        let conductor = fresnel_conductor(wi_h, self.base) * glossy;
+
+        // Light gets through the coat to the diffuse base and back out
+        // again, so both ways lose what the coat reflects
+        let coat = fresnel_dielectric(wi_h, self.ri);
+        let through =
+            (1.0 - fresnel_dielectric(cos_o, self.ri)) * (1.0 - fresnel_dielectric(cos_i, self.ri));
+        let dielectric =
+            Vec3f::new(1.0, 1.0, 1.0) * (coat * glossy) + self.base * (through * cos_i / PI);
+
+        conductor * metallic + dielectric * (1.0 - metallic)
+    }
+
+    pub fn pdf(&self, normal: Vec3f, wo: Vec3f, wi: Vec3f) -> f64 {
+        let cos_i = normal.dot(&wi);
+        if normal.dot(&wo) <= 0.0 || cos_i <= 0.0 {
+            return 0.0;
+        }
+
+        let w = self.specular_weight();
+        w * self.ggx.reflection_pdf(normal, wo, wi) + (1.0 - w) * cos_i / PI
+    }
+
+    // A direction picked with density pdf(), or None if it went below the
+    // surface
+    pub fn sample(&self, normal: Vec3f, wo: Vec3f, stream: &mut SampleStream) -> Option<Vec3f> {
+        let specular = stream.get_1d() < self.specular_weight();
+        let (u, v) = stream.get_2d();
+        let wi = if specular {
+            let h = self.ggx.sample_half(normal, (u, v));
+            h * (2.0 * wo.dot(&h)) - wo
+        } else {
+            // Cosine weighted
+            let (t, b) = normal.orthonormal_basis();
+            let phi = 2.0 * PI * v;
+            t * (u.sqrt() * phi.cos()) + b * (u.sqrt() * phi.sin()) + normal * (1.0 - u).sqrt()
+        };
+
+        if normal.dot(&wi) > 0.0 {
+            Some(wi.make_unit_vector())
+        } else {
+            None
+        }
+    }
+}
+
+// Reflectance of a dielectric for unpolarized light, going from a medium
+// with index 1 into one with index `ri`
+pub fn fresnel_dielectric(cos_i: f64, ri: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin_t2 = (1.0 - cos_i * cos_i) / (ri * ri);
+    if sin_t2 >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin_t2).sqrt();
+    let parallel = (ri * cos_i - cos_t) / (ri * cos_i + cos_t);
+    let perpendicular = (cos_i - ri * cos_t) / (cos_i + ri * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// Schlick's approximation for metals, using their color at normal incidence
+pub fn fresnel_conductor(cos_i: f64, f0: Vec3f) -> Vec3f {
+    let k = (1.0 - cos_i.clamp(0.0, 1.0)).powi(5);
+    f0 + (Vec3f::new(1.0, 1.0, 1.0) - f0) * k
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::microfacet::{fresnel_conductor, fresnel_dielectric, Ggx, Principled};
+    use crate::rng::Pcg32;
+    use crate::sampler::{Independent, SampleStream};
+    use crate::vec::Vec3f;
+    use rand::Rng;
+    use std::f64::consts::PI;
+
+    // Uniformly over the sphere, so estimates are a plain average times 4 pi
+    fn random_direction(rng: &mut Pcg32) -> Vec3f {
+        let z: f64 = rng.gen_range(-1.0, 1.0);
+        let phi = 2.0 * PI * rng.gen_range(0.0, 1.0);
+        let r = (1.0 - z * z).sqrt();
+        Vec3f::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    #[test]
+    fn distribution_is_normalized() {
+        // The projected area of the microfacets adds up to the surface. D
+        // only depends on the angle, so a midpoint rule over its cosine will
+        // do.
+        let n = 100_000;
+        for roughness in [0.2, 0.5, 1.0].iter() {
+            let ggx = Ggx::new(*roughness);
+            let sum: f64 = (0..n)
+                .map(|i| {
+                    let cos = (i as f64 + 0.5) / n as f64;
+                    ggx.d(cos) * cos
+                })
+                .sum();
+            let integral = sum / n as f64 * 2.0 * PI;
+            assert!((integral - 1.0).abs() < 1e-3, "{}", integral);
+        }
+    }
+
+    #[test]
+    fn reflection_pdf_integrates_to_one() {
+        // Minus what goes below the surface
+        let mut rng = Pcg32::new(2, 0);
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let wo = Vec3f::new(0.3, 0.0, 1.0).make_unit_vector();
+        let ggx = Ggx::new(0.5);
+
+        let n = 200_000;
+        let sum: f64 = (0..n)
+            .map(|_| {
+                let wi = random_direction(&mut rng);
+                if wi.z() > 0.0 {
+                    ggx.reflection_pdf(normal, wo, wi)
+                } else {
+                    0.0
+                }
+            })
+            .sum();
+        let integral = sum / n as f64 * 4.0 * PI;
+        assert!(integral > 0.9 && integral < 1.02, "{}", integral);
+
+        let h = ggx.sample_half(normal, (rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0)));
+        assert!((h.length() - 1.0).abs() < 1e-12);
+        assert!(h.z() > 0.0);
+    }
+
+    #[test]
+    fn shadowing() {
+        let ggx = Ggx::new(0.5);
+        assert!((ggx.g1(1.0) - 1.0).abs() < 1e-12);
+        assert!(ggx.g1(0.1) < ggx.g1(0.5));
+        assert_eq!(ggx.g1(-0.5), 0.0);
+        assert_eq!(ggx.g(0.5, 0.5), ggx.g1(0.5) * ggx.g1(0.5));
+    }
+
+    #[test]
+    fn fresnel() {
+        // 4% for glass head on, everything at grazing angles
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-12);
+        assert!(fresnel_dielectric(0.5, 1.5) < fresnel_dielectric(0.1, 1.5));
+
+        let gold = Vec3f::new(1.0, 0.78, 0.34);
+        assert_eq!(fresnel_conductor(1.0, gold), gold);
+        assert_eq!(fresnel_conductor(0.0, gold), Vec3f::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn sampling_matches_eval_and_pdf() {
+        let mut rng = Pcg32::new(3, 0);
+        let mut stream = SampleStream::new(&Independent, 3, 0, 0);
+        let normal = Vec3f::new(0.0, 0.0, 1.0);
+        let wo = Vec3f::new(-0.5, 0.2, 1.0).make_unit_vector();
+
+        for metallic in [0.0, 0.5, 1.0].iter() {
+            let surface = Principled {
+                base: Vec3f::new(1.0, 1.0, 1.0),
+                ggx: Ggx::new(0.4),
+                metallic: *metallic,
+                ri: 1.5,
+            };
+
+            // Estimated by sampling and by integrating over the hemisphere,
+            // it is how much light gets reflected, which can't be more than
+            // arrives
+            let n = 100_000;
+            let mut sampled = 0.0;
+            let mut uniform = 0.0;
+            for _ in 0..n {
+                if let Some(wi) = surface.sample(normal, wo, &mut stream) {
+                    assert!((wi.length() - 1.0).abs() < 1e-9);
+                    sampled += surface.eval(normal, wo, wi).x() / surface.pdf(normal, wo, wi);
+                }
+                let wi = random_direction(&mut rng);
+                uniform += surface.eval(normal, wo, wi).x() * 4.0 * PI;
+            }
+            let (sampled, uniform) = (sampled / n as f64, uniform / n as f64);
+            assert!(sampled <= 1.0 && sampled > 0.7, "{} {}", metallic, sampled);
+            assert!((sampled - uniform).abs() < 0.03, "{} {}", sampled, uniform);
+        }
+
+        // Nothing from below
+        let surface = Principled {
+            base: Vec3f::new(1.0, 1.0, 1.0),
+            ggx: Ggx::new(0.4),
+            metallic: 0.0,
+            ri: 1.5,
+        };
+        let below = Vec3f::new(0.0, 0.0, -1.0);
+        assert_eq!(surface.eval(normal, wo, below), Vec3f::default());
+        assert_eq!(surface.pdf(normal, wo, below), 0.0);
+    }
+}
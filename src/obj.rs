@@ -0,0 +1,201 @@
+use crate::material::Material;
+use crate::texture::Texture;
+use crate::triangle::TriangleMesh;
+use crate::vec::Vec3f;
+use crate::visible::Visible;
+use std::path::Path;
+
+// Illumination models from the MTL spec that involve refraction
+const REFRACTIVE_ILLUM: [u8; 4] = [4, 6, 7, 9];
+
+fn to_vec3f(c: [f32; 3]) -> Vec3f {
+    Vec3f::new(f64::from(c[0]), f64::from(c[1]), f64::from(c[2]))
+}
+
+fn max_component(c: Vec3f) -> f64 {
+    c.x().max(c.y()).max(c.z())
+}
+
+// Maps an MTL material to the closest of ours:
+// * Anything with a non-black Ke becomes a DiffuseLight.
+// * Transparent materials (d < 1 or a refractive illum) become Dielectric
+//   with Ni as refractive index.
+// * Materials whose Ks outweighs Kd become Metal tinted by Ks, with the
+//   fuzz derived from the Ns exponent.
+// * Everything else is Lambertian with Kd as albedo.
+pub fn material_from_mtl(m: &tobj::Material) -> Material {
+    let diffuse = m.diffuse.map(to_vec3f).unwrap_or_default();
+    let specular = m.specular.map(to_vec3f).unwrap_or_default();
+    let transparent = m.dissolve.is_some_and(|d| d < 1.0)
+        || m.illumination_model
+            .is_some_and(|i| REFRACTIVE_ILLUM.contains(&i));
+
+    let emissive = m.emissive.map(to_vec3f).unwrap_or_default();
+    if max_component(emissive) > 0.0 {
+        return Material::DiffuseLight { emit: emissive };
+    }
+
+    if transparent {
+        let ri = m.optical_density.map_or(1.5, f64::from);
+        return Material::Dielectric { ri };
+    }
+
+    if max_component(specular) > max_component(diffuse) {
+        // Roughly the Phong exponent to microfacet roughness conversion
+        let shininess = m.shininess.map_or(0.0, f64::from).max(0.0);
+        let fuzz = (2.0 / (shininess + 2.0)).sqrt().min(1.0);
+        return Material::Metal {
+            albedo: Texture::Solid(specular),
+            fuzz,
+        };
+    }
+
+    Material::Lambertian {
+        albedo: Texture::Solid(diffuse),
+    }
+}
+
+// Loads every object of a Wavefront OBJ file as its own mesh, with the
+// material from the MTL library it references. Faces are triangulated. A
+// referenced MTL library that can't be loaded is an error too, and so is a
+// face pointing past the end of the vertex list.
+pub fn load_obj(path: &Path) -> Result<Vec<TriangleMesh>, tobj::LoadError> {
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ..Default::default()
+    };
+    let (models, materials) = tobj::load_obj(path, &options)?;
+    let materials = materials?;
+
+    let mut meshes = Vec::with_capacity(models.len());
+    for model in models {
+        let mesh = model.mesh;
+        let positions = mesh
+            .positions
+            .chunks(3)
+            .map(|p| to_vec3f([p[0], p[1], p[2]]))
+            .collect();
+        let normals = mesh
+            .normals
+            .chunks(3)
+            .map(|n| to_vec3f([n[0], n[1], n[2]]).make_unit_vector())
+            .collect();
+        let uvs = mesh
+            .texcoords
+            .chunks(2)
+            .map(|t| (f64::from(t[0]), f64::from(t[1])))
+            .collect();
+        let indices = mesh
+            .indices
+            .chunks(3)
+            .map(|i| [i[0] as usize, i[1] as usize, i[2] as usize])
+            .collect();
+
+        let mut tri_mesh = TriangleMesh::new(positions, normals, uvs, indices)
+            .ok_or(tobj::LoadError::FaceVertexOutOfBounds)?;
+        if let Some(m) = mesh.material_id.and_then(|id| materials.get(id)) {
+            tri_mesh.set_material(material_from_mtl(m));
+        }
+        meshes.push(tri_mesh);
+    }
+
+    Ok(meshes)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::material::Material;
+    use crate::obj::{load_obj, material_from_mtl};
+    use crate::ray::Ray;
+    use crate::texture::Texture;
+    use crate::vec::Vec3f;
+    use crate::visible::{HitRecord, Visible};
+    use std::fs;
+
+    #[test]
+    fn mtl_mapping() {
+        let mut m = tobj::Material {
+            diffuse: Some([0.5, 0.25, 0.125]),
+            ..Default::default()
+        };
+        match material_from_mtl(&m) {
+            Material::Lambertian {
+                albedo: Texture::Solid(albedo),
+            } => assert_eq!(albedo, Vec3f::new(0.5, 0.25, 0.125)),
+            _ => panic!("expected a Lambertian material"),
+        }
+
+        m.specular = Some([0.9, 0.9, 0.9]);
+        m.shininess = Some(198.0);
+        match material_from_mtl(&m) {
+            Material::Metal {
+                albedo: Texture::Solid(albedo),
+                fuzz,
+            } => {
+                assert_eq!(albedo.x(), f64::from(0.9_f32));
+                assert_eq!(fuzz, 0.1);
+            }
+            _ => panic!("expected a Metal material"),
+        }
+
+        m.dissolve = Some(0.2);
+        m.optical_density = Some(1.25);
+        match material_from_mtl(&m) {
+            Material::Dielectric { ri } => assert_eq!(ri, 1.25),
+            _ => panic!("expected a Dielectric material"),
+        }
+
+        m.emissive = Some([2.0, 2.0, 1.0]);
+        match material_from_mtl(&m) {
+            Material::DiffuseLight { emit } => assert_eq!(emit, Vec3f::new(2.0, 2.0, 1.0)),
+            _ => panic!("expected a DiffuseLight material"),
+        }
+    }
+
+    #[test]
+    fn load_obj_with_mtl() {
+        let dir = std::env::temp_dir().join(format!("shade-tree-obj-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("quad.mtl"),
+            "newmtl red\nKd 0.8 0.1 0.1\nKs 0.0 0.0 0.0\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("quad.obj"),
+            "mtllib quad.mtl\n\
+             o quad\n\
+             v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\n\
+             vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+             vn 0 0 1\n\
+             usemtl red\n\
+             f 1/1/1 2/2/1 3/3/1 4/4/1\n",
+        )
+        .unwrap();
+
+        let meshes = load_obj(&dir.join("quad.obj")).unwrap();
+        assert_eq!(meshes.len(), 1);
+        // The quad gets triangulated
+        assert_eq!(meshes[0].len(), 2);
+
+        let ray = Ray::new(Vec3f::new(0.5, 0.5, 2.0), Vec3f::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        assert!(meshes[0].hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 2.0);
+        assert_eq!(rec.normal, Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!((rec.u, rec.v), (0.75, 0.75));
+        match rec.material {
+            Material::Lambertian {
+                albedo: Texture::Solid(albedo),
+            } => assert_eq!(albedo.x(), f64::from(0.8_f32)),
+            _ => panic!("expected the MTL material"),
+        }
+
+        // A missing library fails the load instead of losing the materials
+        fs::remove_file(dir.join("quad.mtl")).unwrap();
+        assert!(load_obj(&dir.join("quad.obj")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
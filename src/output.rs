@@ -0,0 +1,364 @@
+use crate::error::{Error, Result};
+use crate::render::Framebuffer;
+use crate::tonemap::ToneMapping;
+use crate::vec::Vec3f;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Scanlines outside these widths can't be run length encoded in .hdr files
+const HDR_RLE_MIN_WIDTH: usize = 8;
+const HDR_RLE_MAX_WIDTH: usize = 0x7fff;
+// Longest run of literal bytes in an .hdr scanline
+const HDR_MAX_DUMP: usize = 128;
+
+// File extensions save_image() knows how to write
+pub const IMAGE_FORMATS: [&str; 5] = ["png", "ppm", "pfm", "exr", "hdr"];
+// Values per line in ASCII PPM files, which should stay under 70 characters
+const PPM_VALUES_PER_LINE: usize = 15;
+
+// Writes the image in the format matching the file extension:
+// * .png: 8 bits per channel, tone mapped and sRGB encoded.
+// * .ppm: binary 8 bit PPM, tone mapped like PNG. Files ending in
+//   .ascii.ppm get the plain text variant instead.
+// * .pfm: Portable FloatMap with linear 32 bit float channels.
+// * .exr: OpenEXR with linear 32 bit float channels.
+// * .hdr: Radiance RGBE with linear colors.
+pub fn save_image(path: &Path, image: &Framebuffer, tone_mapping: &ToneMapping) -> Result<()> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    if !IMAGE_FORMATS.contains(&extension.as_str()) {
+        return Err(Error::UnknownFormat(format!(
+            "unknown image format for {}, expected one of: {}",
+            path.display(),
+            IMAGE_FORMATS.join(", ")
+        )));
+    }
+
+    save_image_as(path, &extension, image, tone_mapping)
+}
+
+// Like save_image(), but in one of IMAGE_FORMATS whatever the extension
+pub fn save_image_as(
+    path: &Path,
+    format: &str,
+    image: &Framebuffer,
+    tone_mapping: &ToneMapping,
+) -> Result<()> {
+    let written = match format {
+        "png" => write_png(path, image, tone_mapping),
+        "ppm" => {
+            let ascii = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .is_some_and(|s| s.to_ascii_lowercase().ends_with(".ascii"));
+            write_ppm(path, image, tone_mapping, ascii)
+        }
+        "pfm" => write_pfm(path, image),
+        "exr" => write_exr(path, image),
+        "hdr" => write_hdr(path, image),
+        _ => {
+            return Err(Error::UnknownFormat(format!(
+                "unknown image format {}, expected one of: {}",
+                format,
+                IMAGE_FORMATS.join(", ")
+            )))
+        }
+    };
+
+    Ok(written?)
+}
+
+// 8 bit RGB bytes from 0xRRGGBB pixels
+pub fn vec_from_hex(orig: Vec<u32>) -> Vec<u8> {
+    let orig_len = orig.len();
+    let mut out = vec![0_u8; orig_len * 3];
+    for c in 0..orig_len {
+        out[c * 3] = (orig[c] >> 16) as u8;
+        out[c * 3 + 1] = ((orig[c] & 0xff00) >> 8) as u8;
+        out[c * 3 + 2] = (orig[c] & 0xff) as u8;
+    }
+
+    out
+}
+
+pub fn write_png(path: &Path, image: &Framebuffer, tone_mapping: &ToneMapping) -> io::Result<()> {
+    let file = File::create(path)?;
+    let w = &mut BufWriter::new(file);
+
+    let mut encoder = png::Encoder::new(w, image.width() as u32, image.height() as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+
+    let data = vec_from_hex(image.to_hex(tone_mapping));
+    writer.write_image_data(data.as_slice())?;
+
+    Ok(())
+}
+
+pub fn write_ppm(
+    path: &Path,
+    image: &Framebuffer,
+    tone_mapping: &ToneMapping,
+    ascii: bool,
+) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut w = BufWriter::new(file);
+    let data = vec_from_hex(image.to_hex(tone_mapping));
+
+    if ascii {
+        writeln!(w, "P3\n{} {}\n255", image.width(), image.height())?;
+        for line in data.chunks(PPM_VALUES_PER_LINE) {
+            let values: Vec<String> = line.iter().map(|v| v.to_string()).collect();
+            writeln!(w, "{}", values.join(" "))?;
+        }
+    } else {
+        write!(w, "P6\n{} {}\n255\n", image.width(), image.height())?;
+        w.write_all(&data)?;
+    }
+
+    w.flush()
+}
+
+// Rows go from the bottom up. The negative scale marks the floats as little
+// endian.
+pub fn write_pfm(path: &Path, image: &Framebuffer) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut w = BufWriter::new(file);
+
+    write!(w, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    for row in image.pixels().chunks(image.width()).rev() {
+        for c in row {
+            for v in [c.r(), c.g(), c.b()].iter() {
+                w.write_all(&(*v as f32).to_le_bytes())?;
+            }
+        }
+    }
+
+    w.flush()
+}
+
+pub fn write_exr(path: &Path, image: &Framebuffer) -> io::Result<()> {
+    exr::prelude::write_rgb_file(path, image.width(), image.height(), |x, y| {
+        let c = image.get(x, y);
+        (c.r() as f32, c.g() as f32, c.b() as f32)
+    })
+    .map_err(|e| match e {
+        exr::error::Error::Io(e) => e,
+        e => io::Error::other(e.to_string()),
+    })
+}
+
+// Shared exponent encoding from the Radiance file format: the mantissas of
+// the three channels share the exponent of the largest one
+fn to_rgbe(c: Vec3f) -> [u8; 4] {
+    let v = c.r().max(c.g()).max(c.b());
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // v = m * 2^e with m in [0.5, 1)
+    let mut e = v.log2().floor() as i32 + 1;
+    let mut m = v / 2_f64.powi(e);
+    // log2() can be off by one close to powers of two
+    if m >= 1.0 {
+        m /= 2.0;
+        e += 1;
+    } else if m < 0.5 {
+        m *= 2.0;
+        e -= 1;
+    }
+    if e > 127 {
+        return [255, 255, 255, 255];
+    }
+
+    let scale = m * 256.0 / v;
+    let channel = |x: f64| (x.max(0.0) * scale) as u8;
+    [
+        channel(c.r()),
+        channel(c.g()),
+        channel(c.b()),
+        (e + 128) as u8,
+    ]
+}
+
+// Run length encoded scanline: each of the four components is written
+// separately, here only as literal dumps of up to 128 bytes. Flat scanlines
+// whose first pixel happens to start with 2, 2 would be mistaken for encoded
+// ones by readers.
+fn write_hdr_scanline<W: Write>(w: &mut W, pixels: &[[u8; 4]]) -> io::Result<()> {
+    let width = pixels.len();
+    w.write_all(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8])?;
+
+    for component in 0..4 {
+        let bytes: Vec<u8> = pixels.iter().map(|p| p[component]).collect();
+        for dump in bytes.chunks(HDR_MAX_DUMP) {
+            w.write_all(&[dump.len() as u8])?;
+            w.write_all(dump)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn write_hdr(path: &Path, image: &Framebuffer) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut w = BufWriter::new(file);
+
+    write!(
+        w,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        image.height(),
+        image.width()
+    )?;
+
+    let rle = (HDR_RLE_MIN_WIDTH..=HDR_RLE_MAX_WIDTH).contains(&image.width());
+    for row in image.pixels().chunks(image.width()) {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(|c| to_rgbe(*c)).collect();
+        if rle {
+            write_hdr_scanline(&mut w, &rgbe)?;
+        } else {
+            for p in rgbe.iter() {
+                w.write_all(p)?;
+            }
+        }
+    }
+
+    w.flush()
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::output::{save_image, save_image_as, to_rgbe};
+    use crate::render::Framebuffer;
+    use crate::tonemap::ToneMapping;
+    use crate::vec::Vec3f;
+    use std::fs;
+
+    fn test_image() -> Framebuffer {
+        let pixels = (0..16 * 2)
+            .map(|i| Vec3f::new(i as f64 * 0.5, 1.0, 100.0))
+            .collect();
+        Framebuffer::new(16, 2, pixels).unwrap()
+    }
+
+    #[test]
+    fn rgbe_encoding() {
+        assert_eq!(to_rgbe(Vec3f::default()), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(Vec3f::new(1.0, 0.5, 0.0)), [128, 64, 0, 129]);
+        assert_eq!(to_rgbe(Vec3f::new(0.0, 0.0, 6.0)), [0, 0, 192, 131]);
+        // Negative values can't be represented
+        assert_eq!(to_rgbe(Vec3f::new(-1.0, 1.0, 1.0)), [0, 128, 128, 129]);
+    }
+
+    #[test]
+    fn ppm_and_pfm() {
+        let dir = std::env::temp_dir().join(format!("shade-tree-ppm-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Black and white on top, then 0.25 and 2.0 below
+        let pixels = vec![
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(1.0, 1.0, 1.0),
+            Vec3f::new(0.25, 0.25, 0.25),
+            Vec3f::new(2.0, 0.0, 0.5),
+        ];
+        let image = Framebuffer::new(2, 2, pixels).unwrap();
+
+        let ppm = dir.join("out.ppm");
+        save_image(&ppm, &image, &ToneMapping::default()).unwrap();
+        let mut expected = b"P6\n2 2\n255\n".to_vec();
+        // sRGB encoded, and the bright pixel clamped
+        expected.extend_from_slice(&[0, 0, 0, 255, 255, 255, 137, 137, 137, 255, 0, 188]);
+        assert_eq!(fs::read(&ppm).unwrap(), expected);
+
+        let ascii = dir.join("out.ascii.ppm");
+        save_image(&ascii, &image, &ToneMapping::default()).unwrap();
+        let text = fs::read_to_string(&ascii).unwrap();
+        assert_eq!(
+            text,
+            "P3\n2 2\n255\n0 0 0 255 255 255 137 137 137 255 0 188\n"
+        );
+
+        let pfm = dir.join("out.pfm");
+        save_image(&pfm, &image, &ToneMapping::default()).unwrap();
+        let data = fs::read(&pfm).unwrap();
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&data[..header.len()], &header[..]);
+        assert_eq!(data.len(), header.len() + 4 * 3 * 4);
+        // The bottom row comes first, floats unclamped
+        let first = f32::from_le_bytes([
+            data[header.len()],
+            data[header.len() + 1],
+            data[header.len() + 2],
+            data[header.len() + 3],
+        ]);
+        assert_eq!(first, 0.25);
+        let fourth = &data[header.len() + 12..header.len() + 16];
+        assert_eq!(
+            f32::from_le_bytes([fourth[0], fourth[1], fourth[2], fourth[3]]),
+            2.0
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_by_extension() {
+        let dir = std::env::temp_dir().join(format!("shade-tree-output-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let image = test_image();
+
+        let hdr = dir.join("out.hdr");
+        save_image(&hdr, &image, &ToneMapping::default()).unwrap();
+        let data = fs::read(&hdr).unwrap();
+        let header = "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 16\n";
+        assert!(data.starts_with(header.as_bytes()));
+        // Two scanlines, each with a 4 byte marker and four 16 byte dumps
+        assert_eq!(data.len(), header.len() + 2 * (4 + 4 * 17));
+
+        let exr = dir.join("out.exr");
+        save_image(&exr, &image, &ToneMapping::default()).unwrap();
+        let read = exr::prelude::read_first_rgba_layer_from_file(
+            &exr,
+            |size, _| vec![(0.0_f32, 0.0_f32, 0.0_f32); size.width() * size.height()],
+            |pixels, pos, (r, g, b, _a): (f32, f32, f32, f32)| {
+                pixels[pos.y() * 16 + pos.x()] = (r, g, b);
+            },
+        )
+        .unwrap();
+        let pixels = &read.layer_data.channel_data.pixels;
+        // Values above 1 survive
+        assert_eq!(pixels[5], (2.5, 1.0, 100.0));
+        assert_eq!(pixels[31], (15.5, 1.0, 100.0));
+
+        let png = dir.join("out.png");
+        save_image(&png, &image, &ToneMapping::default()).unwrap();
+        assert!(fs::metadata(&png).unwrap().len() > 0);
+
+        assert!(matches!(
+            save_image(&dir.join("out.bmp"), &image, &ToneMapping::default()),
+            Err(Error::UnknownFormat(_))
+        ));
+
+        // Unless the format is given
+        let pfm = dir.join("out.img");
+        save_image_as(&pfm, "pfm", &image, &ToneMapping::default()).unwrap();
+        assert!(fs::read(&pfm).unwrap().starts_with(b"PF\n"));
+        assert!(save_image_as(&pfm, "bmp", &image, &ToneMapping::default()).is_err());
+        assert!(matches!(
+            save_image(
+                &dir.join("missing/out.png"),
+                &image,
+                &ToneMapping::default()
+            ),
+            Err(Error::Io(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
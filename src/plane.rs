@@ -0,0 +1,119 @@
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::quad::PARALLEL_EPSILON;
+use crate::ray::Ray;
+use crate::vec::Vec3f;
+use crate::visible::{HitRecord, Visible};
+
+// Infinite plane through `point`. Cheaper and more precise than a huge
+// sphere for grounds. Textures repeat every unit along the plane.
+#[derive(Clone)]
+pub struct Plane {
+    point: Vec3f,
+    normal: Vec3f,
+    // Directions of u and v on the plane
+    tangent: Vec3f,
+    bitangent: Vec3f,
+    material: Material,
+}
+
+impl Plane {
+    pub fn new(point: Vec3f, normal: Vec3f) -> Plane {
+        let normal = normal.make_unit_vector();
+        let (tangent, bitangent) = normal.orthonormal_basis();
+
+        Plane {
+            point,
+            normal,
+            tangent,
+            bitangent,
+            material: Material::default(),
+        }
+    }
+}
+
+impl Visible for Plane {
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        let denom = self.normal.dot(&ray.direction());
+        if denom.abs() < PARALLEL_EPSILON {
+            return false;
+        }
+
+        let t = self.normal.dot(&(self.point - ray.origin())) / denom;
+        if t >= t_max || t <= t_min {
+            return false;
+        }
+
+        let p = ray.point_at(t);
+        let planar = p - self.point;
+        rec.t = t;
+        rec.p = p;
+        rec.normal = self.normal;
+        rec.u = planar.dot(&self.tangent).rem_euclid(1.0);
+        rec.v = planar.dot(&self.bitangent).rem_euclid(1.0);
+        rec.material = &self.material;
+
+        true
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    // Unbounded, so it is tested on its own instead of in the Bvh
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::plane::Plane;
+    use crate::ray::Ray;
+    use crate::vec::Vec3f;
+    use crate::visible::{HitRecord, Visible};
+
+    fn ground() -> Plane {
+        Plane::new(Vec3f::new(0.0, -1.0, 0.0), Vec3f::new(0.0, 2.0, 0.0))
+    }
+
+    #[test]
+    fn plane_hit() {
+        let plane = ground();
+        let mut rec = HitRecord::default();
+
+        let ray = Ray::new(Vec3f::new(100.0, 3.0, -50.0), Vec3f::new(0.0, -1.0, 0.0));
+        assert!(plane.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 4.0);
+        assert_eq!(rec.p, Vec3f::new(100.0, -1.0, -50.0));
+        assert_eq!(rec.normal, Vec3f::new(0.0, 1.0, 0.0));
+        assert!((0.0..1.0).contains(&rec.u) && (0.0..1.0).contains(&rec.v));
+
+        // From below
+        let ray = Ray::new(Vec3f::new(0.0, -3.0, 0.0), Vec3f::new(1.0, 1.0, 0.0));
+        assert!(plane.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.p.y(), -1.0);
+        assert!(plane.bounding_box().is_none());
+    }
+
+    #[test]
+    fn plane_miss() {
+        let plane = ground();
+        let mut rec = HitRecord::default();
+
+        // Pointing away
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 1.0, 0.0));
+        assert!(!plane.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Parallel, just above and in the plane itself
+        let ray = Ray::new(Vec3f::new(0.0, -0.999, 0.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert!(!plane.hit(ray, 0.0001, f64::MAX, &mut rec));
+        let ray = Ray::new(Vec3f::new(0.0, -1.0, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(!plane.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Further than t_max
+        let ray = Ray::new(Vec3f::new(0.0, 3.0, 0.0), Vec3f::new(0.0, -1.0, 0.0));
+        assert!(!plane.hit(ray, 0.0001, 3.5, &mut rec));
+    }
+}
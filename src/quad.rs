@@ -0,0 +1,229 @@
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::rng::Pcg32;
+use crate::vec::Vec3f;
+use crate::visible::{HitRecord, Visible};
+use rand::Rng;
+
+// Rays closer than this to parallel with a flat shape are considered misses
+pub(crate) const PARALLEL_EPSILON: f64 = 1e-8;
+// Flat boxes still get some thickness so the slab test behaves
+pub(crate) const BOX_PADDING: f64 = 1e-4;
+// Corners closer than this along an axis lie in the same axis aligned plane
+const SAME_PLANE_EPSILON: f64 = 1e-9;
+
+// Parallelogram with a corner at `q` and sides `u` and `v`. The normal is
+// u x v, so it faces whoever sees u to v going counter-clockwise.
+#[derive(Clone)]
+pub struct Quad {
+    q: Vec3f,
+    u: Vec3f,
+    v: Vec3f,
+    normal: Vec3f,
+    // Plane equation normal . p = d
+    d: f64,
+    // Used to find the planar coordinates of a point on the plane
+    w: Vec3f,
+    area: f64,
+    material: Material,
+}
+
+impl Quad {
+    pub fn new(q: Vec3f, u: Vec3f, v: Vec3f) -> Quad {
+        let n = u.cross(&v);
+        let normal = n.make_unit_vector();
+
+        Quad {
+            q,
+            u,
+            v,
+            normal,
+            d: normal.dot(&q),
+            w: n / n.dot(&n),
+            area: n.length(),
+            material: Material::default(),
+        }
+    }
+
+    // Rectangle between two opposite corners that share one coordinate,
+    // facing the positive side of that axis. None unless exactly one
+    // coordinate is shared.
+    pub fn axis_aligned(a: Vec3f, b: Vec3f) -> Option<Quad> {
+        let shared: Vec<usize> = (0..3)
+            .filter(|i| (a.get(*i) - b.get(*i)).abs() < SAME_PLANE_EPSILON)
+            .collect();
+        if shared.len() != 1 {
+            return None;
+        }
+        let axis = shared[0];
+        let (i, j) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        let mut corner = Vec3f::default();
+        let mut u = Vec3f::default();
+        let mut v = Vec3f::default();
+        corner.set(axis, a.get(axis));
+        corner.set(i, a.get(i).min(b.get(i)));
+        corner.set(j, a.get(j).min(b.get(j)));
+        u.set(i, (a.get(i) - b.get(i)).abs());
+        v.set(j, (a.get(j) - b.get(j)).abs());
+
+        Some(Quad::new(corner, u, v))
+    }
+}
+
+impl Visible for Quad {
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        let denom = self.normal.dot(&ray.direction());
+        if denom.abs() < PARALLEL_EPSILON {
+            return false;
+        }
+
+        let t = (self.d - self.normal.dot(&ray.origin())) / denom;
+        if t >= t_max || t <= t_min {
+            return false;
+        }
+
+        let p = ray.point_at(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = p;
+        rec.normal = self.normal;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.material = &self.material;
+
+        true
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let pad = Vec3f::new(BOX_PADDING, BOX_PADDING, BOX_PADDING);
+        let b = Aabb::new(self.q, self.q)
+            .grow(self.q + self.u)
+            .grow(self.q + self.v)
+            .grow(self.q + self.u + self.v);
+
+        Some(Aabb::new(b.min() - pad, b.max() + pad))
+    }
+
+    // Uniformly samples a point over the area of the quad
+    fn sample_direction(&self, origin: Vec3f, rng: &mut Pcg32) -> Option<Vec3f> {
+        let p = self.q + self.u * rng.gen_range(0.0, 1.0) + self.v * rng.gen_range(0.0, 1.0);
+
+        Some(p - origin)
+    }
+
+    fn direction_pdf(&self, origin: Vec3f, dir: Vec3f) -> f64 {
+        let ray = Ray::new(origin, dir);
+        let mut rec = HitRecord::default();
+        if !self.hit(ray, 0.0001, f64::MAX, &mut rec) {
+            return 0.0;
+        }
+
+        // Converts the area density 1 / area to solid angle
+        let cosine = ray.direction().dot(&self.normal).abs();
+        rec.t * rec.t / (cosine * self.area)
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::quad::Quad;
+    use crate::ray::Ray;
+    use crate::rng::Pcg32;
+    use crate::vec::Vec3f;
+    use crate::visible::{HitRecord, Visible};
+
+    // Unit square on the y = 1 plane, facing down
+    fn ceiling() -> Quad {
+        Quad::new(
+            Vec3f::new(0.0, 1.0, 0.0),
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 0.0, 1.0),
+        )
+    }
+
+    #[test]
+    fn quad_hit() {
+        let quad = ceiling();
+        let ray = Ray::new(Vec3f::new(0.25, 0.0, 0.5), Vec3f::new(0.0, 1.0, 0.0));
+        let mut rec = HitRecord::default();
+
+        assert!(quad.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 1.0);
+        assert_eq!(rec.p, Vec3f::new(0.25, 1.0, 0.5));
+        assert_eq!(rec.normal, Vec3f::new(0.0, -1.0, 0.0));
+        assert_eq!((rec.u, rec.v), (0.25, 0.5));
+    }
+
+    #[test]
+    fn quad_miss() {
+        let quad = ceiling();
+        let mut rec = HitRecord::default();
+
+        // Outside the sides
+        let ray = Ray::new(Vec3f::new(1.5, 0.0, 0.5), Vec3f::new(0.0, 1.0, 0.0));
+        assert!(!quad.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Parallel, in the plane itself
+        let ray = Ray::new(Vec3f::new(-1.0, 1.0, 0.5), Vec3f::new(1.0, 0.0, 0.0));
+        assert!(!quad.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Pointing away
+        let ray = Ray::new(Vec3f::new(0.5, 0.0, 0.5), Vec3f::new(0.0, -1.0, 0.0));
+        assert!(!quad.hit(ray, 0.0001, f64::MAX, &mut rec));
+    }
+
+    #[test]
+    fn axis_aligned_quad() {
+        let mut rec = HitRecord::default();
+
+        let floor =
+            Quad::axis_aligned(Vec3f::new(2.0, 0.0, -1.0), Vec3f::new(-2.0, 0.0, 3.0)).unwrap();
+        let ray = Ray::new(Vec3f::new(1.0, 1.0, 0.0), Vec3f::new(0.0, -1.0, 0.0));
+        assert!(floor.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.normal, Vec3f::new(0.0, 1.0, 0.0));
+        // u runs along z and v along x
+        assert_eq!((rec.u, rec.v), (0.25, 0.75));
+
+        // Off by a rounding error still counts as the same plane
+        let wall =
+            Quad::axis_aligned(Vec3f::new(0.0, 0.0, 0.3), Vec3f::new(1.0, 1.0, 0.1 * 3.0)).unwrap();
+        let ray = Ray::new(Vec3f::new(0.5, 0.5, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(wall.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.normal, Vec3f::new(0.0, 0.0, 1.0));
+        let ray = Ray::new(Vec3f::new(1.5, 0.5, 0.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(!wall.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Corners of a box, or of a line
+        assert!(Quad::axis_aligned(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(1.0, 1.0, 1.0)).is_none());
+        assert!(Quad::axis_aligned(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn quad_direction_pdf() {
+        let quad = ceiling();
+        let origin = Vec3f::new(0.5, 0.0, 0.5);
+
+        // Straight up the density is distance squared over area
+        assert_eq!(quad.direction_pdf(origin, Vec3f::new(0.0, 1.0, 0.0)), 1.0);
+        assert_eq!(quad.direction_pdf(origin, Vec3f::new(0.0, -1.0, 0.0)), 0.0);
+
+        let mut rng = Pcg32::new(0, 0);
+        for _ in 0..100 {
+            let dir = quad.sample_direction(origin, &mut rng).unwrap();
+            assert!(quad.direction_pdf(origin, dir) > 0.0);
+        }
+    }
+}
@@ -4,13 +4,19 @@ use crate::vec::Vec3f;
 pub struct Ray {
     orig: Vec3f,
     dir: Vec3f,
+    // When, while the shutter is open, the ray is traced
+    time: f64,
 }
 
 impl Ray {
     pub fn new(orig: Vec3f, dir: Vec3f) -> Ray {
+        Ray::at_time(orig, dir, 0.0)
+    }
+
+    pub fn at_time(orig: Vec3f, dir: Vec3f, time: f64) -> Ray {
         let mut dir = dir;
         dir.normalize();
-        Ray { orig, dir }
+        Ray { orig, dir, time }
     }
 
     #[allow(dead_code)]
@@ -26,6 +32,10 @@ impl Ray {
         self.dir
     }
 
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
     pub fn point_at(&self, t: f64) -> Vec3f {
         self.orig + self.dir * t
     }
@@ -0,0 +1,1029 @@
+use crate::camera::Camera;
+use crate::error::{Error, Result};
+use crate::integrator::{integrator_from_name, Integrator, DEFAULT_MAX_DEPTH, INTEGRATOR_NAMES};
+use crate::sampler::{sampler_from_name, SampleStream, Sampler};
+use crate::tile::{tiles, Tile, TileOrder, DEFAULT_TILE_SIZE};
+use crate::tonemap::{luminance, ToneMapping};
+use crate::vec::Vec3f;
+use crate::world::World;
+use indicatif::ProgressBar;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// How an image gets rendered, independently of what is in it
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    // Samples per pixel, or the most any pixel gets with adaptive sampling
+    pub samples: usize,
+    // Bounces after which paths are cut short
+    pub max_depth: usize,
+    // How the linear image is turned into 8 bit colors
+    pub tone_mapping: ToneMapping,
+    // Show a progress bar on the terminal while rendering
+    pub progress: bool,
+    // Renders with the same seed and settings are identical
+    pub seed: u64,
+    // Pixels a side of the square tiles the image is split into, each one
+    // rendered by a single thread
+    pub tile_size: usize,
+    // Order tiles are handed out in, which only changes how a render looks
+    // while it's in progress
+    pub tile_order: TileOrder,
+    // Stops sampling pixels that are already smooth, None to give every
+    // pixel the same number of samples
+    pub adaptive: Option<AdaptiveSampling>,
+}
+
+// When a pixel has had enough samples. Its noise is measured as the standard
+// error of its mean luminance, relative to the mean.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    // Relative error under which a pixel gets no more samples, 0.01 stops
+    // pixels at about 1% noise
+    pub threshold: f64,
+    // Samples every pixel gets before its noise estimate is trusted
+    pub min_samples: usize,
+}
+
+impl Default for AdaptiveSampling {
+    fn default() -> AdaptiveSampling {
+        AdaptiveSampling {
+            threshold: 0.01,
+            min_samples: 16,
+        }
+    }
+}
+
+// Pixels darker than this are held to the error of one this bright, or noisy
+// shadows would never converge
+const DARK_LUMINANCE: f64 = 0.01;
+
+impl RenderSettings {
+    // Fails for settings that can't give an image
+    pub fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::InvalidSettings(format!(
+                "image size must be at least 1x1, got {}x{}",
+                self.width, self.height
+            )));
+        }
+        if self.samples == 0 {
+            return Err(Error::InvalidSettings(
+                "samples per pixel must be at least 1".to_string(),
+            ));
+        }
+        if self.tile_size == 0 {
+            return Err(Error::InvalidSettings(
+                "tile size must be at least 1".to_string(),
+            ));
+        }
+        if let Some(adaptive) = &self.adaptive {
+            if !(adaptive.threshold > 0.0 && adaptive.threshold.is_finite()) {
+                return Err(Error::InvalidSettings(format!(
+                    "adaptive sampling threshold must be a positive number, got {}",
+                    adaptive.threshold
+                )));
+            }
+            // The variance needs at least two samples
+            if adaptive.min_samples < 2 {
+                return Err(Error::InvalidSettings(format!(
+                    "adaptive sampling needs at least 2 samples per pixel, got {}",
+                    adaptive.min_samples
+                )));
+            }
+        }
+        if !self.tone_mapping.exposure.is_finite() {
+            return Err(Error::InvalidSettings(format!(
+                "exposure must be a finite number of stops, got {}",
+                self.tone_mapping.exposure
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            width: 1280,
+            height: 720,
+            samples: 30,
+            max_depth: DEFAULT_MAX_DEPTH,
+            tone_mapping: ToneMapping::default(),
+            progress: true,
+            seed: 0,
+            tile_size: DEFAULT_TILE_SIZE,
+            tile_order: TileOrder::default(),
+            adaptive: None,
+        }
+    }
+}
+
+// Everything a pixel's samples added up to so far
+#[derive(Copy, Clone, Debug, Default)]
+struct Pixel {
+    sum: Vec3f,
+    // Of the luminance of each sample, for the variance
+    sum_sq: f64,
+    samples: usize,
+}
+
+impl Pixel {
+    fn add(&mut self, radiance: Vec3f) {
+        let l = luminance(radiance);
+        self.sum = self.sum + radiance;
+        self.sum_sq += l * l;
+        self.samples += 1;
+    }
+
+    fn mean(&self) -> Vec3f {
+        self.sum * (1.0 / self.samples.max(1) as f64)
+    }
+
+    // Whether the pixel is smooth enough to stop sampling it. Only depends on
+    // the pixel's own samples, so the image stays the same whatever order
+    // pixels are rendered in.
+    fn converged(&self, adaptive: Option<&AdaptiveSampling>) -> bool {
+        let adaptive = match adaptive {
+            Some(adaptive) if self.samples >= adaptive.min_samples => adaptive,
+            _ => return false,
+        };
+
+        let n = self.samples as f64;
+        let mean = luminance(self.sum) / n;
+        let variance = ((self.sum_sq - n * mean * mean) / (n - 1.0)).max(0.0);
+
+        (variance / n).sqrt() <= adaptive.threshold * mean.max(DARK_LUMINANCE)
+    }
+}
+
+// Black through red and yellow to white, as `t` goes from 0 to 1
+fn heat(t: f64) -> Vec3f {
+    let t = t.clamp(0.0, 1.0) * 3.0;
+
+    Vec3f::new(t.min(1.0), (t - 1.0).clamp(0.0, 1.0), (t - 2.0).max(0.0))
+}
+
+// Linear radiance of every pixel, row by row from the top
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3f>,
+}
+
+impl Framebuffer {
+    // None for an empty image or when there isn't one pixel per width *
+    // height
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3f>) -> Option<Framebuffer> {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            return None;
+        }
+
+        Some(Framebuffer {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Vec3f] {
+        &self.pixels
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Vec3f {
+        self.pixels[y * self.width + x]
+    }
+
+    // 0xRRGGBB pixels for display
+    pub fn to_hex(&self, tone_mapping: &ToneMapping) -> Vec<u32> {
+        self.pixels
+            .iter()
+            .map(|c| tone_mapping.to_hex(*c))
+            .collect()
+    }
+}
+
+// A world seen through a camera, ready to be rendered with some settings.
+// Uses the plain path tracer and independent random numbers unless told
+// otherwise.
+//
+// Samples are added up in a floating point buffer, so the image can be
+// looked at or saved at any point. The image is split in tiles that threads
+// pick up in the tile order, either one pass of one sample per pixel at a
+// time, for showing progress, or all of a tile's samples at once.
+pub struct RenderJob {
+    world: World,
+    camera: Camera,
+    settings: RenderSettings,
+    integrator: Box<dyn Integrator>,
+    sampler: Box<dyn Sampler>,
+    tiles: Vec<Tile>,
+    // Samples so far, per pixel
+    accum: Vec<Pixel>,
+    passes: usize,
+}
+
+impl RenderJob {
+    pub fn new(mut world: World, camera: Camera, settings: RenderSettings) -> Result<RenderJob> {
+        settings.validate()?;
+        world.build_acceleration();
+        let integrator = integrator_from_name("path", settings.max_depth).unwrap();
+        let sampler = sampler_from_name("independent", settings.samples).unwrap();
+        let accum = vec![Pixel::default(); settings.width * settings.height];
+        let tiles = tiles(
+            settings.width,
+            settings.height,
+            settings.tile_size,
+            settings.tile_order,
+        );
+
+        Ok(RenderJob {
+            world,
+            camera,
+            settings,
+            integrator,
+            sampler,
+            tiles,
+            accum,
+            passes: 0,
+        })
+    }
+
+    // Picks one of INTEGRATOR_NAMES, cutting paths short after the settings'
+    // max_depth
+    pub fn with_integrator(mut self, name: &str) -> Result<RenderJob> {
+        self.integrator = integrator_from_name(name, self.settings.max_depth).ok_or_else(|| {
+            Error::InvalidSettings(format!(
+                "unknown integrator {}, expected one of: {}",
+                name,
+                INTEGRATOR_NAMES.join(", ")
+            ))
+        })?;
+        self.reset();
+        Ok(self)
+    }
+
+    pub fn with_sampler(mut self, sampler: Box<dyn Sampler>) -> RenderJob {
+        self.sampler = sampler;
+        self.reset();
+        self
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    // Only changes how the image is displayed and saved, so no samples are
+    // lost
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.settings.tone_mapping = tone_mapping;
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    // In the order they get rendered
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    // Passes done so far, which is the samples per pixel unless adaptive
+    // sampling stopped some of them early
+    pub fn samples_done(&self) -> usize {
+        self.passes
+    }
+
+    pub fn is_done(&self) -> bool {
+        let adaptive = self.settings.adaptive.as_ref();
+
+        self.passes >= self.settings.samples
+            || (adaptive.is_some() && self.accum.iter().all(|p| p.converged(adaptive)))
+    }
+
+    // Throws away every sample taken so far
+    pub fn reset(&mut self) {
+        self.passes = 0;
+        for p in self.accum.iter_mut() {
+            *p = Pixel::default();
+        }
+    }
+
+    // Radiance of the sample numbered `pass` of pixel (x, y). Samples are the
+    // same whichever tile or thread takes them, so tiles can be rendered
+    // anywhere.
+    fn sample(&self, x: usize, y: usize, pass: usize) -> Vec3f {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let sampler = self.sampler.as_ref();
+        let mut stream = SampleStream::new(sampler, self.settings.seed, y * width + x, pass);
+        let (dx, dy) = stream.get_2d();
+        let u = (x as f64 + dx) / width as f64;
+        let v = ((height - y) as f64 + dy) / height as f64;
+        let ray = self.camera.get_ray(u, v, &mut stream);
+
+        self.integrator.radiance(ray, &self.world, &mut stream)
+    }
+
+    // Takes more samples of every pixel in the tile, row by row, until each
+    // has `samples` of them or has converged
+    fn trace_tile(&self, tile: &Tile, samples: usize, pixels: &mut [Pixel]) {
+        let adaptive = self.settings.adaptive.as_ref();
+
+        for (i, pixel) in pixels.iter_mut().enumerate() {
+            let (x, y) = (tile.x + i % tile.width, tile.y + i / tile.width);
+            while pixel.samples < samples && !pixel.converged(adaptive) {
+                pixel.add(self.sample(x, y, pixel.samples));
+            }
+        }
+    }
+
+    // Radiance of the samples numbered `passes` of every pixel in the tile,
+    // added up, row by row
+    pub fn render_tile(&self, tile: &Tile, passes: Range<usize>) -> Vec<Vec3f> {
+        let mut sums = vec![Vec3f::default(); tile.pixel_count()];
+        for (i, sum) in sums.iter_mut().enumerate() {
+            let (x, y) = (tile.x + i % tile.width, tile.y + i / tile.width);
+            for pass in passes.clone() {
+                *sum = *sum + self.sample(x, y, pass);
+            }
+        }
+        sums
+    }
+
+    // What the tile has added up so far
+    fn tile_pixels(&self, tile: &Tile) -> Vec<Pixel> {
+        let width = self.settings.width;
+        (tile.y..tile.y + tile.height)
+            .flat_map(|y| self.accum[y * width + tile.x..y * width + tile.x + tile.width].iter())
+            .cloned()
+            .collect()
+    }
+
+    // Gives integrators a look at the world before the first sample
+    fn prepare(&mut self) {
+        if self.passes == 0 {
+            self.integrator.preprocess(&self.world);
+        }
+    }
+
+    // Runs `work` on every tile, with what the tile has added up so far for
+    // it to add more to. Tiles are handed out in order to each thread of the
+    // current pool as it becomes free, so the first ones are done first and
+    // slow tiles don't hold up the rest. No more tiles are handed out once
+    // `stop` is set.
+    fn schedule<F>(&self, stop: &AtomicBool, work: F) -> Vec<(Tile, Vec<Pixel>)>
+    where
+        F: Fn(&Tile, &mut [Pixel]) + Sync,
+    {
+        let next = AtomicUsize::new(0);
+        let done = Mutex::new(Vec::with_capacity(self.tiles.len()));
+
+        rayon::scope(|scope| {
+            for _ in 0..rayon::current_num_threads() {
+                scope.spawn(|_| loop {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let tile = match self.tiles.get(i) {
+                        Some(tile) => tile,
+                        None => break,
+                    };
+                    let mut pixels = self.tile_pixels(tile);
+                    work(tile, &mut pixels);
+                    done.lock().unwrap().push((*tile, pixels));
+                });
+            }
+        });
+
+        done.into_inner().unwrap()
+    }
+
+    // Copies tiles from schedule() back into the image
+    fn store(&mut self, done: Vec<(Tile, Vec<Pixel>)>) {
+        let width = self.settings.width;
+        for (tile, pixels) in done {
+            for (row, y) in (tile.y..tile.y + tile.height).enumerate() {
+                let start = y * width + tile.x;
+                self.accum[start..start + tile.width]
+                    .copy_from_slice(&pixels[row * tile.width..(row + 1) * tile.width]);
+            }
+        }
+    }
+
+    // Adds one more sample to every pixel that hasn't converged, unless the
+    // job is already done
+    pub fn render_pass(&mut self) {
+        self.render_pass_with(|_, _| {});
+    }
+
+    // Like render_pass(), calling `on_tile` with the average radiance of each
+    // tile's pixels, row by row, as soon as the tile is done
+    pub fn render_pass_with<F>(&mut self, on_tile: F)
+    where
+        F: Fn(&Tile, &[Vec3f]) + Sync,
+    {
+        self.render_pass_until(&AtomicBool::new(false), on_tile);
+    }
+
+    // Like render_pass_with(), giving up on the pass once `stop` is set and
+    // the tiles being rendered are done. Returns whether the pass finished.
+    // The tiles that did get done keep their samples, and the next pass
+    // picks up the rest. A job that's done renders nothing and counts as
+    // finished.
+    pub fn render_pass_until<F>(&mut self, stop: &AtomicBool, on_tile: F) -> bool
+    where
+        F: Fn(&Tile, &[Vec3f]) + Sync,
+    {
+        if self.is_done() {
+            return true;
+        }
+
+        self.prepare();
+        let pass = self.passes;
+
+        let done = self.schedule(stop, |tile, pixels| {
+            self.trace_tile(tile, pass + 1, pixels);
+            let means: Vec<Vec3f> = pixels.iter().map(Pixel::mean).collect();
+            on_tile(tile, &means);
+        });
+        let finished = done.len() == self.tiles.len();
+        self.store(done);
+        if finished {
+            self.passes += 1;
+        }
+
+        finished
+    }
+
+    // Average radiance of every pixel so far, before tone mapping
+    pub fn framebuffer(&self) -> Framebuffer {
+        let pixels = self.accum.iter().map(Pixel::mean).collect();
+
+        // The settings were checked when the job was made, so the size is
+        // never zero and there's a pixel for each
+        Framebuffer {
+            width: self.settings.width,
+            height: self.settings.height,
+            pixels,
+        }
+    }
+
+    // Samples taken of every pixel so far, row by row from the top
+    pub fn sample_counts(&self) -> Vec<usize> {
+        self.accum.iter().map(|p| p.samples).collect()
+    }
+
+    // Where adaptive sampling spent its samples: black for none, white for
+    // pixels that got the most samples allowed
+    pub fn heatmap(&self) -> Framebuffer {
+        let max = self.settings.samples as f64;
+        let pixels = self
+            .accum
+            .iter()
+            .map(|p| heat(p.samples as f64 / max))
+            .collect();
+
+        Framebuffer {
+            width: self.settings.width,
+            height: self.settings.height,
+            pixels,
+        }
+    }
+
+    // Current image as one 0xRRGGBB pixel per entry, row by row from the top
+    pub fn pixels(&self) -> Vec<u32> {
+        self.framebuffer().to_hex(&self.settings.tone_mapping)
+    }
+
+    // Renders every remaining sample, one whole tile at a time, and returns
+    // the resulting image. The progress bar moves on with every tile.
+    pub fn render(&mut self) -> Vec<u32> {
+        let settings = &self.settings;
+        if settings.progress {
+            println!(
+                "Running with {} samples per pixel, {} integrator, {} sampler.",
+                settings.samples,
+                self.integrator.name(),
+                self.sampler.name()
+            );
+        }
+
+        let pb = if settings.progress {
+            ProgressBar::new(self.tiles.len() as u64)
+        } else {
+            ProgressBar::hidden()
+        };
+        pb.set_style(
+            indicatif::ProgressStyle::default_bar()
+                .template("[{elapsed_precise}] [{bar:70.cyan/blue}]  {percent}%  ({eta})")
+                .progress_chars("#-"),
+        );
+
+        if !self.is_done() {
+            self.prepare();
+            let samples = self.settings.samples;
+            let done = self.schedule(&AtomicBool::new(false), |tile, pixels| {
+                self.trace_tile(tile, samples, pixels);
+                pb.inc(1);
+            });
+            self.store(done);
+            self.passes = samples;
+        }
+        pb.finish_with_message("Render done");
+
+        if self.settings.progress && self.settings.adaptive.is_some() {
+            let total: usize = self.accum.iter().map(|p| p.samples).sum();
+            println!(
+                "{:.1} samples per pixel on average.",
+                total as f64 / self.accum.len() as f64
+            );
+        }
+
+        self.pixels()
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::camera::Camera;
+    use crate::error::Error;
+    use crate::material::Material;
+    use crate::render::{heat, AdaptiveSampling, Framebuffer, RenderJob, RenderSettings};
+    use crate::sky::Sky;
+    use crate::sphere::{MovingSphere, Sphere};
+    use crate::texture::Texture;
+    use crate::tile::{Tile, TileOrder};
+    use crate::tonemap::{ToneMap, ToneMapping};
+    use crate::vec::Vec3f;
+    use crate::visible::{HitRecord, Visible};
+    use crate::world::World;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    fn job(color: Vec3f, settings: RenderSettings) -> RenderJob {
+        let mut world = World::default();
+        world.set_sky(Sky::Solid { color });
+        let camera = Camera::new(
+            Vec3f::new(0.0, 0.0, 1.0),
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+            0.0,
+            1.0,
+        );
+
+        RenderJob::new(world, camera, settings).unwrap()
+    }
+
+    #[test]
+    fn invalid_settings() {
+        let camera = job(Vec3f::default(), RenderSettings::default()).camera;
+        let invalid = [
+            RenderSettings {
+                width: 0,
+                ..Default::default()
+            },
+            RenderSettings {
+                samples: 0,
+                ..Default::default()
+            },
+            RenderSettings {
+                adaptive: Some(AdaptiveSampling {
+                    threshold: 0.0,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            RenderSettings {
+                adaptive: Some(AdaptiveSampling {
+                    min_samples: 1,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            RenderSettings {
+                tone_mapping: ToneMapping {
+                    exposure: f64::NAN,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ];
+
+        for settings in invalid.iter() {
+            let result = RenderJob::new(World::default(), camera, settings.clone());
+            assert!(matches!(result, Err(Error::InvalidSettings(_))));
+        }
+        assert!(RenderSettings::default().validate().is_ok());
+    }
+
+    #[test]
+    fn render_uses_settings() {
+        let settings = RenderSettings {
+            width: 8,
+            height: 4,
+            samples: 2,
+            progress: false,
+            ..Default::default()
+        };
+        let buffer = job(Vec3f::new(0.25, 0.25, 0.25), settings).render();
+
+        assert_eq!(buffer.len(), 32);
+        // 0.25 is 137 once sRGB encoded
+        assert!(buffer.iter().all(|p| *p == 0x89_8989));
+    }
+
+    #[test]
+    fn render_with_tone_mapping() {
+        let settings = RenderSettings {
+            width: 2,
+            height: 2,
+            samples: 1,
+            tone_mapping: ToneMapping {
+                operator: ToneMap::Reinhard,
+                exposure: 1.0,
+            },
+            progress: false,
+            ..Default::default()
+        };
+        let buffer = job(Vec3f::new(0.25, 0.5, 1.0), settings).render();
+
+        // (0.5, 1, 2) after exposure, (1/3, 1/2, 2/3) after Reinhard
+        assert!(buffer.iter().all(|p| *p == 0x9c_bcd5));
+    }
+
+    #[test]
+    fn integrator_uses_max_depth() {
+        let render = |name: &str, max_depth: usize| {
+            let settings = RenderSettings {
+                width: 4,
+                height: 2,
+                samples: 1,
+                max_depth,
+                progress: false,
+                ..Default::default()
+            };
+            let mut job = job(Vec3f::new(1.0, 1.0, 1.0), settings);
+            let mut ball = Sphere::new(Vec3f::default(), 0.5, HitRecord::default());
+            ball.set_material(Material::Lambertian {
+                albedo: Texture::Solid(Vec3f::new(0.5, 0.5, 0.5)),
+            });
+            job.world.add(Box::new(ball));
+            job.world.build_acceleration();
+            job.with_integrator(name).unwrap().render()
+        };
+
+        for name in ["path", "mis"].iter() {
+            // Without a bounce the ball gets no light at all
+            assert!(render(name, 0).contains(&0), "{}", name);
+            assert!(!render(name, 4).contains(&0), "{}", name);
+        }
+
+        let job = job(Vec3f::default(), RenderSettings::default());
+        assert!(matches!(
+            job.with_integrator("whitted"),
+            Err(Error::InvalidSettings(_))
+        ));
+    }
+
+    #[test]
+    fn shutter_past_the_motion() {
+        // A light moving from out of view to filling all of it by time 1
+        let mut world = World::default();
+        let mut ball = MovingSphere::new(
+            Vec3f::new(0.0, 10.0, 0.0),
+            Vec3f::new(0.0, 0.0, 0.0),
+            0.0,
+            1.0,
+            1.5,
+            HitRecord::default(),
+        );
+        let white = Vec3f::new(1.0, 1.0, 1.0);
+        ball.set_material(Material::DiffuseLight { emit: white });
+        world.add(Box::new(ball));
+        // Enough objects that the Bvh gets used
+        for i in 0..8 {
+            let center = Vec3f::new(i as f64 * 10.0 + 20.0, 0.0, 0.0);
+            world.add(Box::new(Sphere::new(center, 1.0, HitRecord::default())));
+        }
+
+        let camera = Camera::new(
+            Vec3f::new(0.0, 0.0, 3.0),
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            30.0,
+            1.0,
+            0.0,
+            3.0,
+        )
+        .with_shutter(1.0, 3.0);
+        let settings = RenderSettings {
+            width: 3,
+            height: 3,
+            samples: 4,
+            progress: false,
+            ..Default::default()
+        };
+        let mut job = RenderJob::new(world, camera, settings).unwrap();
+        job.render();
+
+        // After time 1 the light stays where it stopped
+        assert!(job.framebuffer().pixels().iter().all(|c| *c == white));
+    }
+
+    #[test]
+    fn passes_accumulate() {
+        let settings = RenderSettings {
+            width: 4,
+            height: 2,
+            samples: 3,
+            progress: false,
+            ..Default::default()
+        };
+        let mut job = job(Vec3f::new(0.25, 2.0, 0.0), settings);
+        assert_eq!(job.samples_done(), 0);
+
+        job.render_pass();
+        job.render_pass();
+        assert_eq!(job.samples_done(), 2);
+        assert!(!job.is_done());
+        // Brighter than white survives in the float buffer
+        assert!(job
+            .framebuffer()
+            .pixels()
+            .iter()
+            .all(|c| *c == Vec3f::new(0.25, 2.0, 0.0)));
+
+        job.render();
+        assert!(job.is_done());
+        assert_eq!(job.samples_done(), 3);
+
+        // One pass too many adds nothing
+        assert!(job.render_pass_until(&AtomicBool::new(false), |_, _| {}));
+        job.render_pass();
+        assert_eq!(job.samples_done(), 3);
+        assert!(job.sample_counts().iter().all(|n| *n == 3));
+
+        job.reset();
+        assert_eq!(job.samples_done(), 0);
+    }
+
+    #[test]
+    fn tiles_report_progress() {
+        let settings = RenderSettings {
+            width: 10,
+            height: 6,
+            samples: 2,
+            progress: false,
+            tile_size: 4,
+            ..Default::default()
+        };
+        let mut job = job(Vec3f::new(0.5, 0.5, 0.5), settings);
+        assert_eq!(job.tiles().len(), 6);
+
+        let seen = Mutex::new(Vec::new());
+        job.render_pass_with(|tile, pixels| {
+            assert_eq!(pixels.len(), tile.pixel_count());
+            assert!(pixels.iter().all(|c| *c == Vec3f::new(0.5, 0.5, 0.5)));
+            seen.lock().unwrap().push(*tile);
+        });
+
+        let mut seen = seen.into_inner().unwrap();
+        let mut expected: Vec<Tile> = job.tiles().to_vec();
+        let key = |t: &Tile| (t.y, t.x);
+        seen.sort_by_key(key);
+        expected.sort_by_key(key);
+        assert_eq!(seen, expected);
+        assert_eq!(job.samples_done(), 1);
+
+        let sums = job.render_tile(&job.tiles()[0], 0..2);
+        assert!(sums.iter().all(|c| *c == Vec3f::new(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn stopped_pass_picks_up_where_it_left() {
+        let settings = RenderSettings {
+            width: 10,
+            height: 6,
+            samples: 2,
+            progress: false,
+            tile_size: 4,
+            ..Default::default()
+        };
+        let mut job = job(Vec3f::new(0.5, 0.5, 0.5), settings);
+        let first = job.tiles()[0];
+
+        // With a single thread nothing else starts once the first tile stops
+        // the pass
+        let stop = AtomicBool::new(false);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let finished = pool
+            .install(|| job.render_pass_until(&stop, |_, _| stop.store(true, Ordering::Relaxed)));
+        assert!(!finished);
+        assert_eq!(job.samples_done(), 0);
+        let counts = job.sample_counts();
+        assert_eq!(counts.iter().sum::<usize>(), first.pixel_count());
+        assert_eq!(counts[first.y * 10 + first.x], 1);
+
+        // The next pass only fills in the tiles that were left out
+        let seen = AtomicUsize::new(0);
+        assert!(job.render_pass_until(&AtomicBool::new(false), |_, _| {
+            seen.fetch_add(1, Ordering::Relaxed);
+        }));
+        assert_eq!(seen.into_inner(), job.tiles().len());
+        assert_eq!(job.samples_done(), 1);
+        assert!(job.sample_counts().iter().all(|c| *c == 1));
+    }
+
+    // A few spheres that exercise every random decision: lens, jitter,
+    // diffuse and fuzzy bounces and glass
+    fn spheres(seed: u64, threads: usize) -> (Vec<u32>, Vec<usize>) {
+        let settings = RenderSettings {
+            samples: 4,
+            ..Default::default()
+        };
+        spheres_with(seed, threads, settings, false)
+    }
+
+    // Either with render() or pass by pass, along with the samples each pixel
+    // got
+    fn spheres_with(
+        seed: u64,
+        threads: usize,
+        settings: RenderSettings,
+        passes: bool,
+    ) -> (Vec<u32>, Vec<usize>) {
+        let mut world = World::default();
+        let materials = [
+            Material::Lambertian {
+                albedo: Texture::Solid(Vec3f::new(0.8, 0.3, 0.3)),
+            },
+            Material::Metal {
+                albedo: Texture::Solid(Vec3f::new(0.8, 0.8, 0.8)),
+                fuzz: 0.3,
+            },
+            Material::Dielectric { ri: 1.5 },
+        ];
+        for (i, m) in materials.iter().enumerate() {
+            let center = Vec3f::new(i as f64 - 1.0, 0.0, -1.0);
+            let mut s = Sphere::new(center, 0.45, HitRecord::default());
+            s.set_material(m.clone());
+            world.add(Box::new(s));
+        }
+
+        let camera = Camera::new(
+            Vec3f::new(0.0, 0.0, 1.0),
+            Vec3f::new(0.0, 0.0, -1.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+            0.1,
+            2.0,
+        );
+        let settings = RenderSettings {
+            width: 32,
+            height: 16,
+            progress: false,
+            seed,
+            ..settings
+        };
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            let mut job = RenderJob::new(world, camera, settings).unwrap();
+            if passes {
+                while !job.is_done() {
+                    job.render_pass();
+                }
+            } else {
+                job.render();
+            }
+            (job.pixels(), job.sample_counts())
+        })
+    }
+
+    #[test]
+    fn framebuffer_rejects_bad_size() {
+        assert!(Framebuffer::new(0, 4, Vec::new()).is_none());
+        assert!(Framebuffer::new(4, 0, Vec::new()).is_none());
+        assert!(Framebuffer::new(2, 2, vec![Vec3f::default(); 5]).is_none());
+        assert!(Framebuffer::new(2, 2, vec![Vec3f::default(); 4]).is_some());
+    }
+
+    #[test]
+    fn same_seed_same_image() {
+        let single = spheres(7, 1);
+
+        assert_eq!(single, spheres(7, 1));
+        assert_eq!(single, spheres(7, 4));
+        assert_ne!(single, spheres(8, 4));
+    }
+
+    #[test]
+    fn tiles_dont_change_the_image() {
+        let reference = spheres(7, 2);
+
+        for (size, order) in [
+            (1, TileOrder::Scanline),
+            (5, TileOrder::Spiral),
+            (8, TileOrder::Hilbert),
+            (64, TileOrder::Spiral),
+        ]
+        .iter()
+        {
+            let settings = RenderSettings {
+                samples: 4,
+                tile_size: *size,
+                tile_order: *order,
+                ..Default::default()
+            };
+            assert_eq!(reference, spheres_with(7, 3, settings.clone(), false));
+            assert_eq!(reference, spheres_with(7, 3, settings, true));
+        }
+    }
+
+    #[test]
+    fn smooth_pixels_stop_early() {
+        let settings = RenderSettings {
+            width: 6,
+            height: 4,
+            samples: 100,
+            progress: false,
+            adaptive: Some(AdaptiveSampling {
+                threshold: 0.01,
+                min_samples: 5,
+            }),
+            ..Default::default()
+        };
+        let mut flat = job(Vec3f::new(0.5, 0.5, 0.5), settings);
+
+        // Nothing varies, so every pixel stops as soon as it can
+        for _ in 0..5 {
+            assert!(!flat.is_done());
+            flat.render_pass();
+        }
+        assert!(flat.is_done());
+        assert!(flat.sample_counts().iter().all(|n| *n == 5));
+        assert!(flat.heatmap().pixels().iter().all(|c| *c == heat(0.05)));
+
+        flat.reset();
+        flat.render();
+        assert!(flat.sample_counts().iter().all(|n| *n == 5));
+        assert!(flat
+            .framebuffer()
+            .pixels()
+            .iter()
+            .all(|c| *c == Vec3f::new(0.5, 0.5, 0.5)));
+    }
+
+    #[test]
+    fn noisy_pixels_get_more_samples() {
+        let settings = RenderSettings {
+            samples: 64,
+            adaptive: Some(AdaptiveSampling {
+                threshold: 0.05,
+                min_samples: 8,
+            }),
+            ..Default::default()
+        };
+        let (image, counts) = spheres_with(7, 1, settings.clone(), false);
+
+        assert_eq!(*counts.iter().min().unwrap(), 8);
+        assert_eq!(*counts.iter().max().unwrap(), 64);
+        let total: usize = counts.iter().sum();
+        assert!(total < 64 * counts.len() / 2, "{}", total);
+
+        // Pixels stop at the same sample however they're rendered
+        let tiled = RenderSettings {
+            tile_size: 5,
+            ..settings.clone()
+        };
+        assert_eq!(
+            (image.clone(), counts.clone()),
+            spheres_with(7, 4, tiled, false)
+        );
+        assert_eq!((image, counts), spheres_with(7, 3, settings, true));
+    }
+}
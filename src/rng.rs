@@ -0,0 +1,133 @@
+use rand::{Error, RngCore};
+
+// PCG32 from pcg-random.org. Small and fast, and unlike the generators in
+// rand its bits are guaranteed not to change between versions. Floats drawn
+// with rand's gen_range() could still change with a new rand release, so
+// renders with the same seed are only identical for the same rand version.
+//
+// Every sample of every pixel gets its own stream, which makes the image
+// independent of how the work is split between threads.
+#[derive(Clone, Debug)]
+pub struct Pcg32 {
+    state: u64,
+    inc: u64,
+}
+
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+// Scrambles the bits of `x` so nearby inputs give unrelated outputs
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// 64 random looking bits that only depend on `bits`
+pub fn hash64(bits: &[u64]) -> u64 {
+    bits.iter().fold(0, |h, b| splitmix64(h ^ b))
+}
+
+// Uniform number in [0, 1) that only depends on `bits`, for the few places
+// that need randomness without a generator at hand
+pub fn hash_unit(bits: &[u64]) -> f64 {
+    (hash64(bits) >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+impl Pcg32 {
+    pub fn new(seed: u64, stream: u64) -> Pcg32 {
+        let mut rng = Pcg32 {
+            state: 0,
+            inc: (stream << 1) | 1,
+        };
+        rng.step();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.step();
+
+        rng
+    }
+
+    // Stream for one sample of one pixel
+    pub fn for_sample(seed: u64, pixel: usize, sample: usize) -> Pcg32 {
+        let key = splitmix64(splitmix64(splitmix64(seed) ^ pixel as u64) ^ sample as u64);
+        Pcg32::new(splitmix64(key), key)
+    }
+
+    fn step(&mut self) {
+        self.state = self.state.wrapping_mul(MULTIPLIER).wrapping_add(self.inc);
+    }
+}
+
+impl RngCore for Pcg32 {
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.step();
+
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        (u64::from(self.next_u32()) << 32) | u64::from(self.next_u32())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::rng::{hash_unit, Pcg32};
+    use rand::{Rng, RngCore};
+
+    #[test]
+    fn pcg32_reference_output() {
+        // First outputs of pcg32-global-demo from the reference implementation
+        let mut rng = Pcg32::new(42, 54);
+        let expected = [
+            0xa15c_02b7,
+            0x7b47_f409,
+            0xba1d_3330,
+            0x83d2_f293,
+            0xbfa4_784b,
+            0xcbed_606e,
+        ];
+        for e in expected.iter() {
+            assert_eq!(rng.next_u32(), *e);
+        }
+    }
+
+    #[test]
+    fn sample_streams_differ() {
+        let first = |mut rng: Pcg32| rng.gen_range(0.0, 1.0);
+
+        let a: f64 = first(Pcg32::for_sample(0, 0, 0));
+        assert_eq!(a, first(Pcg32::for_sample(0, 0, 0)));
+        assert_ne!(a, first(Pcg32::for_sample(0, 1, 0)));
+        assert_ne!(a, first(Pcg32::for_sample(0, 0, 1)));
+        assert_ne!(a, first(Pcg32::for_sample(1, 0, 0)));
+    }
+
+    #[test]
+    fn hashed_numbers() {
+        let a = hash_unit(&[1, 2, 3]);
+        assert_eq!(a, hash_unit(&[1, 2, 3]));
+        assert_ne!(a, hash_unit(&[1, 2, 4]));
+        assert_ne!(a, hash_unit(&[3, 2, 1]));
+
+        let mean = (0..10_000).map(|i| hash_unit(&[i])).sum::<f64>() / 10_000.0;
+        assert!((mean - 0.5).abs() < 0.01);
+        assert!((0..10_000).all(|i| (0.0..1.0).contains(&hash_unit(&[i]))));
+    }
+}
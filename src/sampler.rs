@@ -0,0 +1,511 @@
+use crate::rng::{hash64, Pcg32};
+use rand::Rng;
+
+// Names accepted by sampler_from_name()
+pub const SAMPLER_NAMES: [&str; 4] = ["independent", "stratified", "halton", "sobol"];
+
+// Dimensions taken by the pixel, the lens and the shutter time before the
+// first bounce
+const CAMERA_DIMENSIONS: usize = 5;
+// Dimensions each bounce may take for picking a direction
+const BOUNCE_DIMENSIONS: usize = 3;
+
+// Largest f64 below 1
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// Where the random numbers of every sample come from. Samplers spread the
+// samples of a pixel more evenly than independent random numbers would, so
+// images converge faster.
+//
+// Numbers are looked up by the sample's index in its pixel and by dimension,
+// which is how many numbers the sample used before, so they don't depend on
+// which thread asks for them.
+pub trait Sampler: Send + Sync {
+    // One of SAMPLER_NAMES
+    fn name(&self) -> &'static str;
+
+    // Number in [0, 1) for sample `index` in `dimension`. `seed` is
+    // different for every pixel and dimension, for randomizing the points,
+    // and `rng` belongs to the sample, for anything else random.
+    fn get_1d(&self, index: usize, dimension: usize, seed: u64, rng: &mut Pcg32) -> f64;
+
+    // Point in [0, 1)^2 for sample `index`, in `dimension` and the one
+    // after it
+    fn get_2d(&self, index: usize, dimension: usize, seed: u64, rng: &mut Pcg32) -> (f64, f64);
+}
+
+// `samples` per pixel is what stratified sampling splits [0, 1) into
+pub fn sampler_from_name(name: &str, samples: usize) -> Option<Box<dyn Sampler>> {
+    let sampler: Box<dyn Sampler> = match name {
+        "independent" => Box::new(Independent),
+        "stratified" => Box::new(Stratified::new(samples)),
+        "halton" => Box::new(Halton::new()),
+        "sobol" => Box::new(Sobol),
+        _ => return None,
+    };
+
+    Some(sampler)
+}
+
+// The numbers of one sample of one pixel, handed out one dimension after
+// another. Lights and anything else the sampler isn't used for take theirs
+// from rng().
+pub struct SampleStream<'a> {
+    sampler: &'a dyn Sampler,
+    // Render seed and pixel, mixed
+    seed: u64,
+    index: usize,
+    dimension: usize,
+    rng: Pcg32,
+}
+
+impl<'a> SampleStream<'a> {
+    pub fn new(
+        sampler: &'a dyn Sampler,
+        seed: u64,
+        pixel: usize,
+        index: usize,
+    ) -> SampleStream<'a> {
+        SampleStream {
+            sampler,
+            seed: hash64(&[seed, pixel as u64]),
+            index,
+            dimension: 0,
+            rng: Pcg32::for_sample(seed, pixel, index),
+        }
+    }
+
+    pub fn get_1d(&mut self) -> f64 {
+        let seed = hash64(&[self.seed, self.dimension as u64]);
+        let x = self
+            .sampler
+            .get_1d(self.index, self.dimension, seed, &mut self.rng);
+        self.dimension += 1;
+        x
+    }
+
+    pub fn get_2d(&mut self) -> (f64, f64) {
+        let seed = hash64(&[self.seed, self.dimension as u64]);
+        let p = self
+            .sampler
+            .get_2d(self.index, self.dimension, seed, &mut self.rng);
+        self.dimension += 2;
+        p
+    }
+
+    // Moves on to the dimensions of bounce `depth`, so every sample of the
+    // pixel uses the same dimensions for it, however many the camera and
+    // earlier bounces took
+    pub fn start_bounce(&mut self, depth: usize) {
+        self.dimension = CAMERA_DIMENSIONS + depth * BOUNCE_DIMENSIONS;
+    }
+
+    pub fn rng(&mut self) -> &mut Pcg32 {
+        &mut self.rng
+    }
+}
+
+// Plain uniform random numbers
+pub struct Independent;
+
+impl Sampler for Independent {
+    fn name(&self) -> &'static str {
+        "independent"
+    }
+
+    fn get_1d(&self, _index: usize, _dimension: usize, _seed: u64, rng: &mut Pcg32) -> f64 {
+        rng.gen_range(0.0, 1.0)
+    }
+
+    fn get_2d(&self, _index: usize, _dimension: usize, _seed: u64, rng: &mut Pcg32) -> (f64, f64) {
+        (rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0))
+    }
+}
+
+// Splits [0, 1) into one stratum per sample, and [0, 1)^2 into a grid of
+// about as many cells, with every sample of a pixel jittered inside a
+// different one. Samples past the expected count are independent.
+pub struct Stratified {
+    samples: usize,
+    columns: usize,
+    rows: usize,
+}
+
+impl Stratified {
+    pub fn new(samples: usize) -> Stratified {
+        let samples = samples.max(1);
+        let columns = ((samples as f64).sqrt() as usize).max(1);
+        let rows = samples.div_ceil(columns);
+
+        Stratified {
+            samples,
+            columns,
+            rows,
+        }
+    }
+}
+
+impl Sampler for Stratified {
+    fn name(&self) -> &'static str {
+        "stratified"
+    }
+
+    fn get_1d(&self, index: usize, _dimension: usize, seed: u64, rng: &mut Pcg32) -> f64 {
+        if index >= self.samples {
+            return rng.gen_range(0.0, 1.0);
+        }
+
+        let stratum = permute(index as u32, self.samples as u32, seed as u32);
+        (f64::from(stratum) + rng.gen_range(0.0, 1.0)) / self.samples as f64
+    }
+
+    fn get_2d(&self, index: usize, _dimension: usize, seed: u64, rng: &mut Pcg32) -> (f64, f64) {
+        if index >= self.samples {
+            return (rng.gen_range(0.0, 1.0), rng.gen_range(0.0, 1.0));
+        }
+
+        // A grid with a few more cells than samples leaves some empty, but
+        // which ones is random
+        let cells = (self.columns * self.rows) as u32;
+        let cell = permute(index as u32, cells, seed as u32) as usize;
+        let x = ((cell % self.columns) as f64 + rng.gen_range(0.0, 1.0)) / self.columns as f64;
+        let y = ((cell / self.columns) as f64 + rng.gen_range(0.0, 1.0)) / self.rows as f64;
+        (x, y)
+    }
+}
+
+// Kensler's hashed permutation from "Correlated Multi-Jittered Sampling":
+// where `i` goes in a shuffle of 0..l picked by `p`
+fn permute(i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    // Shuffles within the next power of two, again until it lands below l
+    let mut i = i;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+
+    (i.wrapping_add(p)) % l
+}
+
+// The Halton sequence: the sample index with its digits mirrored around the
+// decimal point, in a different prime base for every dimension. Digits are
+// shuffled per pixel, which keeps the points as evenly spread. Dimensions
+// past the table of primes get independent numbers.
+pub struct Halton {
+    primes: Vec<u64>,
+}
+
+impl Halton {
+    pub fn new() -> Halton {
+        Halton {
+            primes: primes(256),
+        }
+    }
+
+    fn dimension(&self, index: usize, dimension: usize, seed: u64, rng: &mut Pcg32) -> f64 {
+        match self.primes.get(dimension) {
+            Some(&base) => scrambled_radical_inverse(index as u64, base, seed),
+            None => rng.gen_range(0.0, 1.0),
+        }
+    }
+}
+
+impl Default for Halton {
+    fn default() -> Halton {
+        Halton::new()
+    }
+}
+
+impl Sampler for Halton {
+    fn name(&self) -> &'static str {
+        "halton"
+    }
+
+    fn get_1d(&self, index: usize, dimension: usize, seed: u64, rng: &mut Pcg32) -> f64 {
+        self.dimension(index, dimension, seed, rng)
+    }
+
+    fn get_2d(&self, index: usize, dimension: usize, seed: u64, rng: &mut Pcg32) -> (f64, f64) {
+        (
+            self.dimension(index, dimension, seed, rng),
+            self.dimension(index, dimension + 1, hash64(&[seed]), rng),
+        )
+    }
+}
+
+// The first `count` primes
+fn primes(count: usize) -> Vec<u64> {
+    let mut primes: Vec<u64> = Vec::with_capacity(count);
+    let mut n = 2;
+    while primes.len() < count {
+        if primes
+            .iter()
+            .take_while(|p| *p * *p <= n)
+            .all(|p| n % p != 0)
+        {
+            primes.push(n);
+        }
+        n += 1;
+    }
+
+    primes
+}
+
+// `index` written in `base` and mirrored around the point, with every digit
+// shuffled by a permutation picked by `seed` and its position. The zeros
+// after the last digit get shuffled too, until they're too small to matter.
+fn scrambled_radical_inverse(index: u64, base: u64, seed: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut index = index;
+    let mut weight = inv_base;
+    let mut result = 0.0;
+    let mut position = 0;
+
+    while weight > 1e-15 {
+        let digit = (index % base) as u32;
+        let p = hash64(&[seed, position]) as u32;
+        result += f64::from(permute(digit, base as u32, p)) * weight;
+
+        index /= base;
+        weight *= inv_base;
+        position += 1;
+    }
+
+    result.min(ONE_MINUS_EPSILON)
+}
+
+// The first two dimensions of the Sobol sequence, Owen scrambled as in
+// Burley's "Practical Hash-based Owen Scrambling". Every 1D or 2D request
+// shuffles the order of the points differently instead of moving on to
+// higher dimensions, so there are as many dimensions as needed and they
+// don't correlate.
+pub struct Sobol;
+
+impl Sampler for Sobol {
+    fn name(&self) -> &'static str {
+        "sobol"
+    }
+
+    fn get_1d(&self, index: usize, _dimension: usize, seed: u64, _rng: &mut Pcg32) -> f64 {
+        let i = owen_scramble(index as u32, seed as u32);
+        to_unit(owen_scramble(i.reverse_bits(), (seed >> 32) as u32))
+    }
+
+    fn get_2d(&self, index: usize, _dimension: usize, seed: u64, _rng: &mut Pcg32) -> (f64, f64) {
+        let i = owen_scramble(index as u32, seed as u32);
+        let more = hash64(&[seed]);
+
+        (
+            to_unit(owen_scramble(i.reverse_bits(), (seed >> 32) as u32)),
+            to_unit(owen_scramble(sobol_second(i), more as u32)),
+        )
+    }
+}
+
+// Second dimension of the Sobol sequence, whose direction numbers are each
+// the previous one xor itself shifted by one
+fn sobol_second(index: u32) -> u32 {
+    let mut index = index;
+    let mut v = 1 << 31;
+    let mut result = 0;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+
+    result
+}
+
+// Flips each bit of `x` depending on the bits above it, randomly picked by
+// `seed`, which shuffles points without breaking up the strata they fill
+fn owen_scramble(x: u32, seed: u32) -> u32 {
+    // Laine and Karras' permutation works from the low bits up
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+fn to_unit(x: u32) -> f64 {
+    f64::from(x) / (1_u64 << 32) as f64
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::sampler::{
+        permute, primes, sampler_from_name, SampleStream, Sampler, SAMPLER_NAMES,
+    };
+
+    fn sampler(name: &str, samples: usize) -> Box<dyn Sampler> {
+        sampler_from_name(name, samples).unwrap()
+    }
+
+    // Whether every cell of a columns x rows grid holds exactly one point
+    fn one_per_cell(points: &[(f64, f64)], columns: usize, rows: usize) -> bool {
+        let mut count = vec![0; columns * rows];
+        for (x, y) in points.iter() {
+            let cell = (y * rows as f64) as usize * columns + (x * columns as f64) as usize;
+            count[cell] += 1;
+        }
+
+        count.iter().all(|c| *c == 1)
+    }
+
+    #[test]
+    fn numbers_stay_in_range() {
+        for name in SAMPLER_NAMES.iter() {
+            let sampler = sampler(name, 16);
+            assert_eq!(sampler.name(), *name);
+
+            // More samples than the stratified sampler expects, and more
+            // dimensions than there are Halton primes
+            for index in 0..40 {
+                let mut stream = SampleStream::new(sampler.as_ref(), 3, 17, index);
+                for _ in 0..300 {
+                    let x = stream.get_1d();
+                    let (y, z) = stream.get_2d();
+                    assert!([x, y, z].iter().all(|v| (0.0..1.0).contains(v)), "{}", name);
+                }
+            }
+        }
+        assert!(sampler_from_name("random", 16).is_none());
+    }
+
+    #[test]
+    fn samples_of_a_pixel_are_stratified() {
+        let first = |sampler: &dyn Sampler, count: usize, pixel: usize| -> Vec<(f64, f64)> {
+            (0..count)
+                .map(|i| SampleStream::new(sampler, 0, pixel, i).get_2d())
+                .collect()
+        };
+
+        for pixel in 0..20 {
+            for name in ["stratified", "sobol"].iter() {
+                let points = first(sampler(name, 16).as_ref(), 16, pixel);
+                assert!(one_per_cell(&points, 4, 4), "{} {}", name, pixel);
+            }
+            // Sobol points also fill every 2 x 8, 8 x 2 and 16 x 1 grid
+            let points = first(sampler("sobol", 16).as_ref(), 16, pixel);
+            for (columns, rows) in [(16, 1), (8, 2), (2, 8), (1, 16)].iter() {
+                assert!(one_per_cell(&points, *columns, *rows), "sobol {}", pixel);
+            }
+
+            // Bases 2 and 3
+            let points = first(sampler("halton", 6).as_ref(), 6, pixel);
+            assert!(one_per_cell(&points, 2, 3), "halton {}", pixel);
+
+            // Some cells of a 3 x 4 grid stay empty with 10 samples
+            let stratified = sampler("stratified", 10);
+            let points = first(stratified.as_ref(), 10, pixel);
+            let mut cells: Vec<_> = points
+                .iter()
+                .map(|(x, y)| ((x * 3.0) as usize, (y * 4.0) as usize))
+                .collect();
+            cells.sort();
+            cells.dedup();
+            assert_eq!(cells.len(), 10);
+        }
+    }
+
+    #[test]
+    fn pixels_and_dimensions_differ() {
+        for name in SAMPLER_NAMES.iter() {
+            let sampler = sampler(name, 16);
+            let point = |seed, pixel, dimension| {
+                let mut stream = SampleStream::new(sampler.as_ref(), seed, pixel, 0);
+                stream.start_bounce(dimension);
+                stream.get_2d()
+            };
+
+            let p = point(0, 0, 0);
+            assert_eq!(p, point(0, 0, 0));
+            assert_ne!(p, point(0, 1, 0), "{}", name);
+            assert_ne!(p, point(1, 0, 0), "{}", name);
+            // Independent numbers come one after another whatever the
+            // dimension
+            if *name != "independent" {
+                assert_ne!(p, point(0, 0, 1), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn converge_faster_than_independent() {
+        // Mean squared error estimating the integral of x * y, 1/4, with
+        // 64 samples in each of many pixels, at the first bounce
+        let error = |name: &str| -> f64 {
+            let sampler = sampler(name, 64);
+            let pixels = 200;
+            let total: f64 = (0..pixels)
+                .map(|pixel| {
+                    let sum: f64 = (0..64)
+                        .map(|i| {
+                            let mut stream = SampleStream::new(sampler.as_ref(), 1, pixel, i);
+                            stream.start_bounce(0);
+                            let (x, y) = stream.get_2d();
+                            x * y
+                        })
+                        .sum();
+                    (sum / 64.0 - 0.25).powi(2)
+                })
+                .sum();
+            total / pixels as f64
+        };
+
+        // Halton only does well once there are more samples than its bases,
+        // 13 and 17 this early on
+        let independent = error("independent");
+        for name in ["stratified", "halton", "sobol"].iter() {
+            let e = error(name);
+            assert!(e * 4.0 < independent, "{} {} {}", name, e, independent);
+        }
+        // Owen scrambled Sobol points converge faster than plain strata
+        assert!(error("sobol") * 10.0 < error("stratified"));
+    }
+
+    #[test]
+    fn permutations() {
+        for l in [1, 2, 5, 16, 100].iter() {
+            for p in 0..10 {
+                let mut shuffled: Vec<u32> = (0..*l).map(|i| permute(i, *l, p)).collect();
+                shuffled.sort();
+                assert_eq!(shuffled, (0..*l).collect::<Vec<u32>>());
+            }
+        }
+
+        assert_eq!(primes(6), vec![2, 3, 5, 7, 11, 13]);
+    }
+}
@@ -0,0 +1,1185 @@
+use crate::camera::Camera;
+use crate::cuboid::Cuboid;
+use crate::cylinder::{Cone, Cylinder};
+use crate::disk::Disk;
+use crate::error;
+use crate::integrator::{DEFAULT_MAX_DEPTH, INTEGRATOR_NAMES};
+use crate::material::Material;
+use crate::medium::ConstantMedium;
+use crate::obj;
+use crate::plane::Plane;
+use crate::quad::Quad;
+use crate::render::{AdaptiveSampling, RenderJob, RenderSettings};
+use crate::sampler::{sampler_from_name, Sampler, SAMPLER_NAMES};
+use crate::sky::Sky;
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{ImageTexture, Texture};
+use crate::tile::{TileOrder, DEFAULT_TILE_SIZE, TILE_ORDER_NAMES};
+use crate::tonemap::{ToneMap, ToneMapping, TONE_MAP_NAMES};
+use crate::triangle::Triangle;
+use crate::vec::Vec3f;
+use crate::visible::{HitRecord, Visible};
+use crate::world::World;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use toml::Spanned;
+
+// A scene file looks like this:
+//
+//   [camera]
+//   look_from = [12.0, 1.0, 3.0]
+//   look_at = [-4.0, 0.2, -1.0]
+//   vfov = 20.0
+//   aperture = 0.4
+//
+//   [sky]
+//   type = "solid"
+//   color = [0.0, 0.0, 0.0]
+//
+//   [render]
+//   width = 640
+//   height = 360
+//   samples = 50
+//   integrator = "mis"
+//   sampler = "sobol"
+//   max_depth = 20
+//   seed = 7
+//   tone_map = "aces"
+//   exposure = 0.5
+//   tile_size = 16
+//   tile_order = "hilbert"
+//   adaptive_threshold = 0.01
+//   min_samples = 16
+//
+//   [[objects]]
+//   type = "sphere"
+//   center = [0.0, 1.0, 0.0]
+//   radius = 1.0
+//   material = { type = "dielectric", ri = 1.5 }
+//
+// See the scenes/ directory for complete examples.
+
+pub struct Scene {
+    pub world: World,
+    pub camera: Camera,
+    pub settings: RenderSettings,
+    // One of INTEGRATOR_NAMES
+    pub integrator: String,
+    pub sampler: Box<dyn Sampler>,
+}
+
+impl Scene {
+    // Fails if the settings were changed to something that can't be rendered
+    pub fn into_job(self) -> error::Result<RenderJob> {
+        Ok(RenderJob::new(self.world, self.camera, self.settings)?
+            .with_integrator(&self.integrator)?
+            .with_sampler(self.sampler))
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    // The file isn't valid TOML or doesn't match the scene layout
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    // The file parsed but one of the objects can't be built
+    Object {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Parse {
+                line,
+                column,
+                message,
+            }
+            | SceneError::Object {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    camera: CameraDesc,
+    #[serde(default)]
+    render: RenderDesc,
+    #[serde(default)]
+    sky: SkyDesc,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDesc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    look_from: [f64; 3],
+    look_at: [f64; 3],
+    #[serde(default = "default_vup")]
+    vup: [f64; 3],
+    vfov: f64,
+    #[serde(default)]
+    aperture: f64,
+    // Defaults to the distance between look_from and look_at
+    focus_distance: Option<f64>,
+    // Defaults to width / height
+    aspect: Option<f64>,
+    // Moving spheres go from center0 at time 0 to center1 at time 1, so
+    // both are in [0, 1]. Both default to 0, which means no motion blur.
+    shutter_open: Option<Spanned<f64>>,
+    shutter_close: Option<Spanned<f64>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RenderDesc {
+    width: usize,
+    height: usize,
+    samples: usize,
+    // One of INTEGRATOR_NAMES, "path" if missing
+    integrator: Option<Spanned<String>>,
+    // One of SAMPLER_NAMES, "independent" if missing
+    sampler: Option<Spanned<String>>,
+    max_depth: usize,
+    seed: u64,
+    // One of TONE_MAP_NAMES, "clamp" if missing
+    tone_map: Option<Spanned<String>>,
+    exposure: f64,
+    tile_size: usize,
+    // One of TILE_ORDER_NAMES, "spiral" if missing
+    tile_order: Option<Spanned<String>>,
+    // Turns on adaptive sampling, with `samples` as the most per pixel
+    adaptive_threshold: Option<f64>,
+    min_samples: usize,
+}
+
+impl Default for RenderDesc {
+    fn default() -> RenderDesc {
+        RenderDesc {
+            width: 1280,
+            height: 720,
+            samples: 30,
+            integrator: None,
+            sampler: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            seed: 0,
+            tone_map: None,
+            exposure: 0.0,
+            tile_size: DEFAULT_TILE_SIZE,
+            tile_order: None,
+            adaptive_threshold: None,
+            min_samples: AdaptiveSampling::default().min_samples,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum SkyDesc {
+    Gradient { bottom: [f64; 3], top: [f64; 3] },
+    Solid { color: [f64; 3] },
+}
+
+impl Default for SkyDesc {
+    fn default() -> SkyDesc {
+        SkyDesc::Gradient {
+            bottom: [1.0, 1.0, 1.0],
+            top: [0.5, 0.7, 1.0],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        #[serde(default)]
+        material: MaterialDesc,
+    },
+    // Moves from center0 at time 0 to center1 at time 1
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        radius: f64,
+        #[serde(default)]
+        material: MaterialDesc,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        #[serde(default)]
+        material: MaterialDesc,
+    },
+    // Parallelogram with a corner at `corner` and sides `u` and `v`
+    Quad {
+        corner: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        #[serde(default)]
+        material: MaterialDesc,
+    },
+    // Infinite plane through `point`
+    Plane {
+        point: [f64; 3],
+        normal: [f64; 3],
+        #[serde(default)]
+        material: MaterialDesc,
+    },
+    Disk {
+        center: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        #[serde(default)]
+        material: MaterialDesc,
+    },
+    // Axis aligned box between two opposite corners
+    Cuboid {
+        min: [f64; 3],
+        max: [f64; 3],
+        #[serde(default)]
+        material: MaterialDesc,
+    },
+    // Upright, standing on a disk centered at `base`
+    Cylinder {
+        base: [f64; 3],
+        radius: f64,
+        height: f64,
+        #[serde(default)]
+        material: MaterialDesc,
+    },
+    Cone {
+        base: [f64; 3],
+        radius: f64,
+        height: f64,
+        #[serde(default)]
+        material: MaterialDesc,
+    },
+    // Fog filling a closed `boundary` object, isotropic and white unless
+    // given a material
+    Medium {
+        boundary: Box<ObjectDesc>,
+        density: f64,
+        material: Option<MaterialDesc>,
+    },
+    // Wavefront OBJ file, relative to the scene file. Without a material the
+    // ones from its MTL library are used.
+    Mesh {
+        path: String,
+        material: Option<MaterialDesc>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: TextureDesc,
+    },
+    Metal {
+        albedo: TextureDesc,
+        #[serde(default)]
+        fuzz: f64,
+    },
+    Dielectric {
+        ri: f64,
+    },
+    DiffuseLight {
+        emit: [f64; 3],
+    },
+    Isotropic {
+        albedo: TextureDesc,
+    },
+    Microfacet {
+        albedo: TextureDesc,
+        roughness: f64,
+        #[serde(default)]
+        metallic: f64,
+        #[serde(default = "default_ri")]
+        ri: f64,
+    },
+}
+
+impl Default for MaterialDesc {
+    fn default() -> MaterialDesc {
+        MaterialDesc::Lambertian {
+            albedo: TextureDesc::Color([0.8, 0.8, 0.8]),
+        }
+    }
+}
+
+// A plain color, or a table with the type of pattern
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureDesc {
+    Color([f64; 3]),
+    Pattern(PatternDesc),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum PatternDesc {
+    Checker {
+        even: Box<TextureDesc>,
+        odd: Box<TextureDesc>,
+        #[serde(default = "default_scale")]
+        scale: f64,
+    },
+    Noise {
+        #[serde(default = "default_scale")]
+        scale: f64,
+    },
+    // PNG file, relative to the scene file
+    Image {
+        path: String,
+    },
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_ri() -> f64 {
+    1.5
+}
+
+fn default_vup() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn vec3f(v: [f64; 3]) -> Vec3f {
+    Vec3f::new(v[0], v[1], v[2])
+}
+
+impl SkyDesc {
+    fn build(&self) -> Sky {
+        match *self {
+            SkyDesc::Gradient { bottom, top } => Sky::Gradient {
+                bottom: vec3f(bottom),
+                top: vec3f(top),
+            },
+            SkyDesc::Solid { color } => Sky::Solid {
+                color: vec3f(color),
+            },
+        }
+    }
+}
+
+impl TextureDesc {
+    fn build(&self, base_dir: &Path) -> Result<Texture, String> {
+        let pattern = match self {
+            TextureDesc::Color(color) => return Ok(Texture::Solid(vec3f(*color))),
+            TextureDesc::Pattern(pattern) => pattern,
+        };
+
+        match pattern {
+            PatternDesc::Checker { even, odd, scale } => {
+                if *scale <= 0.0 {
+                    return Err(format!("checker scale must be positive, got {}", scale));
+                }
+                Ok(Texture::Checker {
+                    even: Arc::new(even.build(base_dir)?),
+                    odd: Arc::new(odd.build(base_dir)?),
+                    scale: *scale,
+                })
+            }
+            PatternDesc::Noise { scale } => Ok(Texture::noise(*scale)),
+            PatternDesc::Image { path } => {
+                let full_path: PathBuf = base_dir.join(path);
+                let image = ImageTexture::load(&full_path)
+                    .map_err(|e| format!("could not load image {}: {}", full_path.display(), e))?;
+                Ok(Texture::Image(Arc::new(image)))
+            }
+        }
+    }
+}
+
+impl MaterialDesc {
+    fn build(&self, base_dir: &Path) -> Result<Material, String> {
+        Ok(match self {
+            MaterialDesc::Lambertian { albedo } => Material::Lambertian {
+                albedo: albedo.build(base_dir)?,
+            },
+            MaterialDesc::Metal { albedo, fuzz } => Material::Metal {
+                albedo: albedo.build(base_dir)?,
+                fuzz: *fuzz,
+            },
+            MaterialDesc::Dielectric { ri } => Material::Dielectric { ri: *ri },
+            MaterialDesc::DiffuseLight { emit } => Material::DiffuseLight { emit: vec3f(*emit) },
+            MaterialDesc::Isotropic { albedo } => Material::Isotropic {
+                albedo: albedo.build(base_dir)?,
+            },
+            MaterialDesc::Microfacet {
+                albedo,
+                roughness,
+                metallic,
+                ri,
+            } => {
+                if !(0.0..=1.0).contains(roughness) || !(0.0..=1.0).contains(metallic) {
+                    return Err(format!(
+                        "microfacet roughness and metallic must be in [0, 1], got {} and {}",
+                        roughness, metallic
+                    ));
+                }
+                Material::Microfacet {
+                    albedo: albedo.build(base_dir)?,
+                    roughness: *roughness,
+                    metallic: *metallic,
+                    ri: *ri,
+                }
+            }
+        })
+    }
+}
+
+// Translates a byte offset into a 1-based line and column
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let column = before[line_start..].chars().count() + 1;
+
+    (line, column)
+}
+
+fn object_error(text: &str, span: Range<usize>, message: String) -> SceneError {
+    let (line, column) = line_column(text, span.start);
+    SceneError::Object {
+        line,
+        column,
+        message,
+    }
+}
+
+// Lights on shapes that know how to sample themselves are registered as such
+fn add_sampled(world: &mut World, obj: Box<dyn Visible>, material: &MaterialDesc) {
+    match material {
+        MaterialDesc::DiffuseLight { .. } => world.add_light(obj),
+        _ => world.add(obj),
+    }
+}
+
+fn check_upright(shape: &str, radius: f64, height: f64) -> Result<(), String> {
+    if radius <= 0.0 || height <= 0.0 {
+        return Err(format!(
+            "{} radius and height must be positive, got {} and {}",
+            shape, radius, height
+        ));
+    }
+
+    Ok(())
+}
+
+fn add_object(world: &mut World, obj: &ObjectDesc, base_dir: &Path) -> Result<(), String> {
+    match obj {
+        ObjectDesc::Sphere {
+            center,
+            radius,
+            material,
+        } => {
+            if *radius <= 0.0 {
+                return Err(format!("sphere radius must be positive, got {}", radius));
+            }
+            let mut sphere = Sphere::new(vec3f(*center), *radius, HitRecord::default());
+            sphere.set_material(material.build(base_dir)?);
+            add_sampled(world, Box::new(sphere), material);
+        }
+        ObjectDesc::MovingSphere {
+            center0,
+            center1,
+            radius,
+            material,
+        } => {
+            if *radius <= 0.0 {
+                return Err(format!("sphere radius must be positive, got {}", radius));
+            }
+            let mut sphere = MovingSphere::new(
+                vec3f(*center0),
+                vec3f(*center1),
+                0.0,
+                1.0,
+                *radius,
+                HitRecord::default(),
+            );
+            sphere.set_material(material.build(base_dir)?);
+            world.add(Box::new(sphere));
+        }
+        ObjectDesc::Triangle { vertices, material } => {
+            let mut tri = Triangle::new(vec3f(vertices[0]), vec3f(vertices[1]), vec3f(vertices[2]));
+            tri.set_material(material.build(base_dir)?);
+            world.add(Box::new(tri));
+        }
+        ObjectDesc::Quad {
+            corner,
+            u,
+            v,
+            material,
+        } => {
+            if vec3f(*u).cross(&vec3f(*v)).squared_length() == 0.0 {
+                return Err("quad sides can't be zero or parallel".to_string());
+            }
+            let mut quad = Quad::new(vec3f(*corner), vec3f(*u), vec3f(*v));
+            quad.set_material(material.build(base_dir)?);
+            add_sampled(world, Box::new(quad), material);
+        }
+        ObjectDesc::Plane {
+            point,
+            normal,
+            material,
+        } => {
+            if vec3f(*normal).squared_length() == 0.0 {
+                return Err("plane normal can't be zero".to_string());
+            }
+            let mut plane = Plane::new(vec3f(*point), vec3f(*normal));
+            plane.set_material(material.build(base_dir)?);
+            world.add(Box::new(plane));
+        }
+        ObjectDesc::Disk {
+            center,
+            normal,
+            radius,
+            material,
+        } => {
+            if *radius <= 0.0 {
+                return Err(format!("disk radius must be positive, got {}", radius));
+            }
+            if vec3f(*normal).squared_length() == 0.0 {
+                return Err("disk normal can't be zero".to_string());
+            }
+            let mut disk = Disk::new(vec3f(*center), vec3f(*normal), *radius);
+            disk.set_material(material.build(base_dir)?);
+            add_sampled(world, Box::new(disk), material);
+        }
+        ObjectDesc::Cuboid { min, max, material } => {
+            if (0..3).any(|i| min[i] == max[i]) {
+                return Err("cuboid min and max can't share a coordinate".to_string());
+            }
+            let mut cuboid = Cuboid::new(vec3f(*min), vec3f(*max));
+            cuboid.set_material(material.build(base_dir)?);
+            world.add(Box::new(cuboid));
+        }
+        ObjectDesc::Cylinder {
+            base,
+            radius,
+            height,
+            material,
+        } => {
+            check_upright("cylinder", *radius, *height)?;
+            let mut cylinder = Cylinder::new(vec3f(*base), *radius, *height);
+            cylinder.set_material(material.build(base_dir)?);
+            world.add(Box::new(cylinder));
+        }
+        ObjectDesc::Cone {
+            base,
+            radius,
+            height,
+            material,
+        } => {
+            check_upright("cone", *radius, *height)?;
+            let mut cone = Cone::new(vec3f(*base), *radius, *height);
+            cone.set_material(material.build(base_dir)?);
+            world.add(Box::new(cone));
+        }
+        ObjectDesc::Medium {
+            boundary,
+            density,
+            material,
+        } => {
+            if *density <= 0.0 {
+                return Err(format!("medium density must be positive, got {}", density));
+            }
+            let mut inside = World::default();
+            add_object(&mut inside, boundary, base_dir)?;
+            let mut medium = ConstantMedium::new(Box::new(inside), *density);
+            if let Some(m) = material {
+                medium.set_material(m.build(base_dir)?);
+            }
+            world.add(Box::new(medium));
+        }
+        ObjectDesc::Mesh { path, material } => {
+            let full_path: PathBuf = base_dir.join(path);
+            let meshes = obj::load_obj(&full_path)
+                .map_err(|e| format!("could not load mesh {}: {}", full_path.display(), e))?;
+            let material = match material {
+                Some(m) => Some(m.build(base_dir)?),
+                None => None,
+            };
+            for mut mesh in meshes {
+                if let Some(m) = &material {
+                    mesh.set_material(m.clone());
+                }
+                world.add(Box::new(mesh));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Shutter times, checked against the 0 to 1 that moving spheres move in
+fn shutter(text: &str, cam: &CameraDesc) -> Result<(f64, f64), SceneError> {
+    let error = |time: &Spanned<f64>, message: String| {
+        let (line, column) = line_column(text, time.span().start);
+        SceneError::Parse {
+            line,
+            column,
+            message,
+        }
+    };
+
+    for time in cam.shutter_open.iter().chain(cam.shutter_close.iter()) {
+        if !(0.0..=1.0).contains(time.get_ref()) {
+            let message = format!(
+                "shutter times must be between 0 and 1, got {}",
+                time.get_ref()
+            );
+            return Err(error(time, message));
+        }
+    }
+
+    let time = |t: &Option<Spanned<f64>>| t.as_ref().map_or(0.0, |t| *t.get_ref());
+    let (open, close) = (time(&cam.shutter_open), time(&cam.shutter_close));
+    if open > close {
+        let at = cam
+            .shutter_close
+            .as_ref()
+            .or(cam.shutter_open.as_ref())
+            .unwrap();
+        let message = format!(
+            "shutter_close must not be before shutter_open, got {} and {}",
+            close, open
+        );
+        return Err(error(at, message));
+    }
+
+    Ok((open, close))
+}
+
+fn unknown_name(text: &str, name: &Spanned<String>, what: &str, names: &[&str]) -> SceneError {
+    let (line, column) = line_column(text, name.span().start);
+    SceneError::Parse {
+        line,
+        column,
+        message: format!(
+            "unknown {} `{}`, expected one of: {}",
+            what,
+            name.get_ref(),
+            names.join(", ")
+        ),
+    }
+}
+
+// Parses a scene from its text. Relative paths inside it are resolved
+// against `base_dir`.
+pub fn parse_scene(text: &str, base_dir: &Path) -> Result<Scene, SceneError> {
+    let desc: SceneDesc = toml::from_str(text).map_err(|e| {
+        let (line, column) = line_column(text, e.span().map_or(0, |s| s.start));
+        SceneError::Parse {
+            line,
+            column,
+            message: e.message().to_string(),
+        }
+    })?;
+
+    let render = desc.render;
+    let integrator = match &render.integrator {
+        Some(name) if INTEGRATOR_NAMES.contains(&name.get_ref().as_str()) => name.get_ref().clone(),
+        Some(name) => return Err(unknown_name(text, name, "integrator", &INTEGRATOR_NAMES)),
+        None => "path".to_string(),
+    };
+    let sampler = match &render.sampler {
+        Some(name) => sampler_from_name(name.get_ref(), render.samples)
+            .ok_or_else(|| unknown_name(text, name, "sampler", &SAMPLER_NAMES))?,
+        None => sampler_from_name("independent", render.samples).unwrap(),
+    };
+    let tone_map = match &render.tone_map {
+        Some(name) => ToneMap::from_name(name.get_ref())
+            .ok_or_else(|| unknown_name(text, name, "tone map", &TONE_MAP_NAMES))?,
+        None => ToneMap::default(),
+    };
+    let tile_order = match &render.tile_order {
+        Some(name) => TileOrder::from_name(name.get_ref())
+            .ok_or_else(|| unknown_name(text, name, "tile order", &TILE_ORDER_NAMES))?,
+        None => TileOrder::default(),
+    };
+
+    let cam = desc.camera;
+    let (shutter_open, shutter_close) = shutter(text, &cam)?;
+    let look_from = vec3f(cam.look_from);
+    let look_at = vec3f(cam.look_at);
+    let camera = Camera::new(
+        look_from,
+        look_at,
+        vec3f(cam.vup),
+        cam.vfov,
+        cam.aspect
+            .unwrap_or(render.width as f64 / render.height as f64),
+        cam.aperture,
+        cam.focus_distance
+            .unwrap_or_else(|| (look_from - look_at).length()),
+    )
+    .with_shutter(shutter_open, shutter_close);
+
+    let mut world = World::default();
+    world.set_sky(desc.sky.build());
+    for obj in desc.objects.iter() {
+        add_object(&mut world, obj.get_ref(), base_dir)
+            .map_err(|message| object_error(text, obj.span(), message))?;
+    }
+
+    Ok(Scene {
+        world,
+        camera,
+        settings: RenderSettings {
+            width: render.width,
+            height: render.height,
+            samples: render.samples,
+            max_depth: render.max_depth,
+            seed: render.seed,
+            tone_mapping: ToneMapping {
+                operator: tone_map,
+                exposure: render.exposure,
+            },
+            tile_size: render.tile_size,
+            tile_order,
+            adaptive: render.adaptive_threshold.map(|threshold| AdaptiveSampling {
+                threshold,
+                min_samples: render.min_samples,
+            }),
+            ..Default::default()
+        },
+        integrator,
+        sampler,
+    })
+}
+
+pub fn load_scene(path: &Path) -> error::Result<Scene> {
+    let text = fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    Ok(parse_scene(&text, base_dir)?)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::render::AdaptiveSampling;
+    use crate::scene::{load_scene, parse_scene, SceneError};
+    use crate::tile::{TileOrder, DEFAULT_TILE_SIZE};
+    use crate::tonemap::ToneMap;
+    use crate::vec::Vec3f;
+    use crate::visible::{HitRecord, Visible};
+    use std::path::Path;
+
+    const SIMPLE: &str = r#"
+[camera]
+look_from = [0.0, 0.0, 5.0]
+look_at = [0.0, 0.0, 0.0]
+vfov = 40.0
+
+[render]
+width = 200
+height = 100
+samples = 4
+integrator = "mis"
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = { type = "metal", albedo = [0.8, 0.8, 0.8], fuzz = 0.1 }
+
+[[objects]]
+type = "triangle"
+vertices = [[-1.0, -1.0, -3.0], [1.0, -1.0, -3.0], [0.0, 1.0, -3.0]]
+"#;
+
+    fn parse_error(text: &str) -> (usize, usize, String) {
+        match parse_scene(text, Path::new(".")) {
+            Err(SceneError::Parse {
+                line,
+                column,
+                message,
+            })
+            | Err(SceneError::Object {
+                line,
+                column,
+                message,
+            }) => (line, column, message),
+            Ok(_) => panic!("scene should not parse"),
+        }
+    }
+
+    #[test]
+    fn parse_simple_scene() {
+        let scene = parse_scene(SIMPLE, Path::new(".")).unwrap();
+        assert_eq!(
+            (
+                scene.settings.width,
+                scene.settings.height,
+                scene.settings.samples
+            ),
+            (200, 100, 4)
+        );
+        assert_eq!(scene.integrator, "mis");
+
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        assert!(scene.world.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 4.0);
+
+        // Just below the sphere there is only the triangle
+        let ray = Ray::new(Vec3f::new(0.0, -1.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(scene.world.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 8.0);
+    }
+
+    #[test]
+    fn lights_and_sky() {
+        let text = format!(
+            "{}\n[sky]\ntype = \"solid\"\ncolor = [0.0, 0.0, 0.0]\n\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0.0, 5.0, 0.0]\nradius = 1.0\n\
+             material = {{ type = \"diffuse_light\", emit = [4.0, 4.0, 4.0] }}\n",
+            SIMPLE
+        );
+        let scene = parse_scene(&text, Path::new(".")).unwrap();
+        assert_eq!(
+            scene.world.sky().color(Vec3f::new(0.0, 1.0, 0.0)),
+            Vec3f::default()
+        );
+
+        let ray = Ray::new(Vec3f::new(0.0, 10.0, 0.0), Vec3f::new(0.0, -1.0, 0.0));
+        let mut rec = HitRecord::default();
+        assert!(scene.world.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.material.emitted(), Vec3f::new(4.0, 4.0, 4.0));
+        assert!(scene.world.has_lights());
+    }
+
+    #[test]
+    fn primitives() {
+        let text = format!(
+            "{}\n[[objects]]\ntype = \"plane\"\npoint = [0.0, -2.0, 0.0]\nnormal = [0.0, 1.0, 0.0]\n\
+             [[objects]]\ntype = \"cuboid\"\nmin = [3.0, 0.0, 0.0]\nmax = [4.0, 1.0, 1.0]\n\
+             [[objects]]\ntype = \"cylinder\"\nbase = [6.0, 0.0, 0.0]\nradius = 0.5\nheight = 1.0\n\
+             [[objects]]\ntype = \"cone\"\nbase = [9.0, 0.0, 0.0]\nradius = 0.5\nheight = 1.0\n\
+             [[objects]]\ntype = \"disk\"\ncenter = [0.0, 8.0, 0.0]\nnormal = [0.0, -1.0, 0.0]\nradius = 1.0\n\
+             material = {{ type = \"diffuse_light\", emit = [1.0, 1.0, 1.0] }}\n",
+            SIMPLE
+        );
+        let scene = parse_scene(&text, Path::new(".")).unwrap();
+        assert!(scene.world.has_lights());
+
+        // Straight down onto each of them, and the plane everywhere else
+        let mut rec = HitRecord::default();
+        for (x, y) in [(3.5, 1.0), (6.0, 1.0), (9.0, 0.5), (12.0, -2.0)].iter() {
+            let ray = Ray::new(Vec3f::new(*x, 5.0, 0.25), Vec3f::new(0.0, -1.0, 0.0));
+            assert!(scene.world.hit(ray, 0.0001, f64::MAX, &mut rec));
+            assert!((rec.p.y() - y).abs() < 1e-9, "{} {}", x, rec.p.y());
+        }
+
+        let text = text.replace("height = 1.0", "height = 0.0");
+        let (line, _, message) = parse_error(&text);
+        assert_eq!(line, 31);
+        assert!(message.contains("height"), "{}", message);
+    }
+
+    #[test]
+    fn degenerate_quad() {
+        let quad = |u: &str, v: &str| {
+            format!(
+                "{}\n[[objects]]\ntype = \"quad\"\ncorner = [0.0, 5.0, 0.0]\nu = {}\nv = {}\n\
+                 material = {{ type = \"diffuse_light\", emit = [1.0, 1.0, 1.0] }}\n",
+                SIMPLE, u, v
+            )
+        };
+        assert!(parse_scene(&quad("[1.0, 0.0, 0.0]", "[0.0, 0.0, 1.0]"), Path::new(".")).is_ok());
+
+        let (line, _, message) = parse_error(&quad("[0.0, 0.0, 0.0]", "[0.0, 0.0, 1.0]"));
+        assert_eq!(line, 23);
+        assert!(message.contains("quad sides"), "{}", message);
+        let (_, _, message) = parse_error(&quad("[1.0, 0.0, 0.0]", "[-2.0, 0.0, 0.0]"));
+        assert!(message.contains("parallel"), "{}", message);
+    }
+
+    #[test]
+    fn flat_cuboid() {
+        let cuboid = |max: &str| {
+            format!(
+                "{}\n[[objects]]\ntype = \"cuboid\"\nmin = [3.0, 0.0, 0.0]\nmax = {}\n",
+                SIMPLE, max
+            )
+        };
+        assert!(parse_scene(&cuboid("[4.0, 1.0, 1.0]"), Path::new(".")).is_ok());
+
+        for max in ["[3.0, 1.0, 1.0]", "[4.0, 0.0, 1.0]", "[4.0, 1.0, 0.0]"].iter() {
+            let (line, _, message) = parse_error(&cuboid(max));
+            assert_eq!(line, 23);
+            assert!(message.contains("share a coordinate"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn motion_blur() {
+        let text = format!(
+            "{}\n[[objects]]\ntype = \"moving_sphere\"\ncenter0 = [5.0, 0.0, 0.0]\n\
+             center1 = [5.0, 4.0, 0.0]\nradius = 1.0\n",
+            SIMPLE.replace("vfov = 40.0", "vfov = 40.0\nshutter_close = 1.0")
+        );
+        let scene = parse_scene(&text, Path::new(".")).unwrap();
+
+        let mut rec = HitRecord::default();
+        let at = |time| Ray::at_time(Vec3f::new(5.0, 4.0, 5.0), Vec3f::new(0.0, 0.0, -1.0), time);
+        assert!(!scene.world.hit(at(0.0), 0.0001, f64::MAX, &mut rec));
+        assert!(scene.world.hit(at(1.0), 0.0001, f64::MAX, &mut rec));
+
+        let shutter =
+            |times: &str| SIMPLE.replace("vfov = 40.0", &format!("vfov = 40.0\n{}", times));
+        let (line, column, message) = parse_error(&shutter("shutter_close = 2.0"));
+        assert_eq!((line, column), (6, 17));
+        assert!(message.contains("between 0 and 1"), "{}", message);
+        let (line, _, message) = parse_error(&shutter("shutter_open = 0.75\nshutter_close = 0.25"));
+        assert_eq!(line, 7);
+        assert!(message.contains("before shutter_open"), "{}", message);
+        let (_, _, message) = parse_error(&shutter("shutter_open = -0.5"));
+        assert!(message.contains("between 0 and 1"), "{}", message);
+    }
+
+    #[test]
+    fn fog() {
+        let text = format!(
+            "{}\n[[objects]]\ntype = \"medium\"\ndensity = 1000.0\n\
+             material = {{ type = \"isotropic\", albedo = [0.5, 0.5, 0.5] }}\n\
+             boundary = {{ type = \"sphere\", center = [5.0, 0.0, 0.0], radius = 1.0 }}\n",
+            SIMPLE
+        );
+        let scene = parse_scene(&text, Path::new(".")).unwrap();
+
+        let ray = Ray::new(Vec3f::new(5.0, 0.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        assert!(scene.world.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!(rec.t > 4.0 && rec.t < 4.1);
+        assert_eq!(rec.material.albedo(&rec), Vec3f::new(0.5, 0.5, 0.5));
+
+        let text = text.replace("density = 1000.0", "density = 0.0");
+        let (_, _, message) = parse_error(&text);
+        assert!(message.contains("density"), "{}", message);
+    }
+
+    #[test]
+    fn microfacet() {
+        let text = format!(
+            "{}\n[[objects]]\ntype = \"sphere\"\ncenter = [5.0, 0.0, 0.0]\nradius = 1.0\n\
+             material = {{ type = \"microfacet\", albedo = [1.0, 0.8, 0.3], roughness = 0.3, metallic = 1.0 }}\n",
+            SIMPLE
+        );
+        let scene = parse_scene(&text, Path::new(".")).unwrap();
+
+        let ray = Ray::new(Vec3f::new(5.0, 0.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        assert!(scene.world.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!(matches!(
+            rec.material,
+            Material::Microfacet { metallic, ri, .. } if *metallic == 1.0 && *ri == 1.5
+        ));
+
+        let text = text.replace("roughness = 0.3", "roughness = 2.0");
+        let (_, _, message) = parse_error(&text);
+        assert!(message.contains("roughness"), "{}", message);
+    }
+
+    #[test]
+    fn load_errors() {
+        let missing = load_scene(Path::new("scenes/missing.toml"));
+        assert!(matches!(missing, Err(Error::Io(_))));
+
+        let path =
+            std::env::temp_dir().join(format!("shade-tree-scene-{}.toml", std::process::id()));
+        std::fs::write(&path, "[camera]\nlook_from = 3\n").unwrap();
+        let broken = load_scene(&path);
+        std::fs::remove_file(&path).unwrap();
+        match broken {
+            Err(Error::Scene(SceneError::Parse { line, .. })) => assert_eq!(line, 2),
+            _ => panic!("scene should not parse"),
+        }
+
+        // Settings that parse but can't be rendered
+        let scene =
+            parse_scene(&SIMPLE.replace("width = 200", "width = 0"), Path::new(".")).unwrap();
+        assert!(matches!(scene.into_job(), Err(Error::InvalidSettings(_))));
+    }
+
+    #[test]
+    fn render_section_is_optional() {
+        let text =
+            "[camera]\nlook_from = [0.0, 0.0, 1.0]\nlook_at = [0.0, 0.0, 0.0]\nvfov = 90.0\n";
+        let scene = parse_scene(text, Path::new(".")).unwrap();
+        assert_eq!(
+            (
+                scene.settings.width,
+                scene.settings.height,
+                scene.settings.samples
+            ),
+            (1280, 720, 30)
+        );
+        assert_eq!(scene.integrator, "path");
+        assert_eq!(scene.sampler.name(), "independent");
+    }
+
+    #[test]
+    fn syntax_error_position() {
+        let text = "[camera]\nlook_from = [0.0, 0.0, 1.0]\nlook_at = = 3\n";
+        let (line, column, _) = parse_error(text);
+        assert_eq!((line, column), (3, 11));
+    }
+
+    #[test]
+    fn unknown_material_position() {
+        let text = SIMPLE.replace("type = \"metal\"", "type = \"plastic\"");
+        let (line, column, message) = parse_error(&text);
+        // Points at the object the material belongs to
+        assert_eq!((line, column), (13, 1));
+        assert!(message.contains("plastic"), "{}", message);
+    }
+
+    #[test]
+    fn invalid_object_position() {
+        let text = SIMPLE.replace("radius = 1.0", "radius = -1.0");
+        let (line, column, message) = parse_error(&text);
+        assert_eq!((line, column), (13, 1));
+        assert!(message.contains("radius"), "{}", message);
+    }
+
+    #[test]
+    fn missing_mesh_file() {
+        let text = format!(
+            "{}\n[[objects]]\ntype = \"mesh\"\npath = \"does-not-exist.obj\"\n",
+            SIMPLE
+        );
+        let (line, _, message) = parse_error(&text);
+        assert_eq!(line, 23);
+        assert!(message.contains("does-not-exist.obj"), "{}", message);
+    }
+
+    #[test]
+    fn textures() {
+        let text = SIMPLE.replace(
+            "albedo = [0.8, 0.8, 0.8]",
+            "albedo = { type = \"checker\", even = [1.0, 0.0, 0.0], odd = { type = \"noise\" }, scale = 4.0 }",
+        );
+        let scene = parse_scene(&text, Path::new(".")).unwrap();
+
+        let mut rec = HitRecord::default();
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(scene.world.hit(ray, 0.001, f64::MAX, &mut rec));
+        assert_eq!(rec.material.albedo(&rec), Vec3f::new(1.0, 0.0, 0.0));
+
+        let text = SIMPLE.replace(
+            "albedo = [0.8, 0.8, 0.8]",
+            "albedo = { type = \"image\", path = \"does-not-exist.png\" }",
+        );
+        let (line, _, message) = parse_error(&text);
+        assert_eq!(line, 13);
+        assert!(message.contains("does-not-exist.png"), "{}", message);
+    }
+
+    #[test]
+    fn tone_mapping() {
+        let text = SIMPLE.replace(
+            "integrator",
+            "tone_map = \"reinhard\"\nexposure = -1.0\nintegrator",
+        );
+        let scene = parse_scene(&text, Path::new(".")).unwrap();
+        assert_eq!(scene.settings.tone_mapping.operator, ToneMap::Reinhard);
+        assert_eq!(scene.settings.tone_mapping.exposure, -1.0);
+
+        let text = SIMPLE.replace("integrator", "tone_map = \"filmic\"\nintegrator");
+        let (line, column, message) = parse_error(&text);
+        assert_eq!((line, column), (11, 12));
+        assert!(message.contains("reinhard"), "{}", message);
+    }
+
+    #[test]
+    fn tiles() {
+        let scene = parse_scene(SIMPLE, Path::new(".")).unwrap();
+        assert_eq!(scene.settings.tile_size, DEFAULT_TILE_SIZE);
+        assert_eq!(scene.settings.tile_order, TileOrder::Spiral);
+
+        let text = SIMPLE.replace(
+            "integrator",
+            "tile_size = 8\ntile_order = \"hilbert\"\nintegrator",
+        );
+        let scene = parse_scene(&text, Path::new(".")).unwrap();
+        assert_eq!(scene.settings.tile_size, 8);
+        assert_eq!(scene.settings.tile_order, TileOrder::Hilbert);
+
+        let text = SIMPLE.replace("integrator", "tile_order = \"random\"\nintegrator");
+        let (line, column, message) = parse_error(&text);
+        assert_eq!((line, column), (11, 14));
+        assert!(message.contains("spiral"), "{}", message);
+    }
+
+    #[test]
+    fn adaptive_sampling() {
+        let scene = parse_scene(SIMPLE, Path::new(".")).unwrap();
+        assert_eq!(scene.settings.adaptive, None);
+
+        let text = SIMPLE.replace(
+            "integrator",
+            "adaptive_threshold = 0.02\nmin_samples = 4\nintegrator",
+        );
+        let scene = parse_scene(&text, Path::new(".")).unwrap();
+        assert_eq!(
+            scene.settings.adaptive,
+            Some(AdaptiveSampling {
+                threshold: 0.02,
+                min_samples: 4
+            })
+        );
+
+        let text = SIMPLE.replace("integrator", "adaptive_threshold = -1.0\nintegrator");
+        let scene = parse_scene(&text, Path::new(".")).unwrap();
+        assert!(matches!(scene.into_job(), Err(Error::InvalidSettings(_))));
+    }
+
+    #[test]
+    fn samplers() {
+        let text = SIMPLE.replace("integrator", "sampler = \"halton\"\nintegrator");
+        let scene = parse_scene(&text, Path::new(".")).unwrap();
+        assert_eq!(scene.sampler.name(), "halton");
+
+        let text = SIMPLE.replace("integrator", "sampler = \"random\"\nintegrator");
+        let (line, column, message) = parse_error(&text);
+        assert_eq!((line, column), (11, 11));
+        assert!(message.contains("sobol"), "{}", message);
+    }
+
+    #[test]
+    fn unknown_integrator_position() {
+        let text = SIMPLE.replace("integrator = \"mis\"", "integrator = \"whitted\"");
+        let (line, column, message) = parse_error(&text);
+        assert_eq!((line, column), (11, 14));
+        assert!(message.contains("whitted"), "{}", message);
+        assert!(message.contains("hit_count"), "{}", message);
+    }
+}
@@ -0,0 +1,33 @@
+use crate::vec::Vec3f;
+
+// What rays that escape the scene see
+#[derive(Copy, Clone)]
+pub enum Sky {
+    // Blends from `bottom` looking straight down to `top` looking straight up
+    Gradient { bottom: Vec3f, top: Vec3f },
+    // The same color in every direction. Black for scenes lit only by lights
+    Solid { color: Vec3f },
+}
+
+impl Sky {
+    pub fn color(&self, dir: Vec3f) -> Vec3f {
+        match *self {
+            Sky::Gradient { bottom, top } => {
+                let t = 0.5 * (dir.y() + 1.0);
+
+                // Linear interpolation: blended value = ((1 - t) * start_value) + (t * end_value)
+                bottom * (1.0 - t) + top * t
+            }
+            Sky::Solid { color } => color,
+        }
+    }
+}
+
+impl Default for Sky {
+    fn default() -> Sky {
+        Sky::Gradient {
+            bottom: Vec3f::new(1.0, 1.0, 1.0),
+            top: Vec3f::new(0.5, 0.7, 1.0),
+        }
+    }
+}
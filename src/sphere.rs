@@ -1,60 +1,268 @@
+use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::rng::Pcg32;
 use crate::vec::Vec3f;
 use crate::visible::{HitRecord, Visible};
+use rand::Rng;
+use std::f64::consts::PI;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Sphere {
     center: Vec3f,
     radius: f64,
-    record: HitRecord,
+    material: Material,
 }
 
 impl Sphere {
+    // Made of the record's material
     pub fn new(center: Vec3f, radius: f64, record: HitRecord) -> Sphere {
         Sphere {
             center,
             radius,
-            record,
+            material: record.material.clone(),
         }
     }
+
+    // Cosine of the half angle of the cone the sphere covers seen from
+    // `origin`, None when `origin` is inside
+    fn cone_cos_max(&self, origin: Vec3f) -> Option<f64> {
+        let dist_squared = (self.center - origin).squared_length();
+        let radius_squared = self.radius * self.radius;
+        if dist_squared <= radius_squared {
+            return None;
+        }
+
+        let cos_max = (1.0 - radius_squared / dist_squared).sqrt();
+        // Too far away to be told apart from a point
+        if cos_max >= 1.0 {
+            return None;
+        }
+
+        Some(cos_max)
+    }
+}
+
+// Longitude and latitude of a point on the unit sphere, both in [0, 1].
+// u goes around the Y axis starting at -X, v from the bottom pole to the top.
+fn sphere_uv(p: Vec3f) -> (f64, f64) {
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+
+    (phi / (2.0 * PI), theta / PI)
+}
+
+// Fills in everything but the material when `ray` hits the sphere between
+// t_min and t_max
+fn hit_sphere(
+    center: Vec3f,
+    radius: f64,
+    ray: Ray,
+    t_min: f64,
+    t_max: f64,
+    rec: &mut HitRecord,
+) -> bool {
+    let oc = ray.origin() - center;
+    let a = ray.direction().dot(&ray.direction());
+    let b = oc.dot(&ray.direction());
+    let c = oc.dot(&oc) - (radius * radius);
+    let discriminant = (b * b) - (a * c);
+    if discriminant <= 0.0 {
+        return false;
+    }
+
+    for temp in [
+        (-b - discriminant.sqrt()) / a,
+        (-b + discriminant.sqrt()) / a,
+    ]
+    .iter()
+    {
+        if *temp < t_max && *temp > t_min {
+            rec.t = *temp;
+            rec.p = ray.point_at(*temp);
+            rec.normal = rec.p - center;
+            rec.normal.normalize();
+            let (u, v) = sphere_uv(rec.normal);
+            rec.u = u;
+            rec.v = v;
+
+            return true;
+        }
+    }
+
+    false
 }
 
 impl Visible for Sphere {
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
-        let oc = ray.origin() - self.center;
-        let a = ray.direction().dot(&ray.direction());
-        let b = oc.dot(&ray.direction());
-        let c = oc.dot(&oc) - (self.radius * self.radius);
-        let discriminant = (b * b) - (a * c);
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        if !hit_sphere(self.center, self.radius, ray, t_min, t_max, rec) {
+            return false;
+        }
+        rec.material = &self.material;
+
+        true
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3f::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+
+    // Samples the cone of directions the sphere covers uniformly
+    fn sample_direction(&self, origin: Vec3f, rng: &mut Pcg32) -> Option<Vec3f> {
+        let cos_max = self.cone_cos_max(origin)?;
+        let r1: f64 = rng.gen_range(0.0, 1.0);
+        let r2: f64 = rng.gen_range(0.0, 1.0);
+
+        let z = 1.0 + r2 * (cos_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+
+        let w = (self.center - origin).make_unit_vector();
+        let (u, v) = w.orthonormal_basis();
 
-        if discriminant > 0.0 {
-            rec.material = self.record.material;
+        Some(u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * z)
+    }
+
+    fn direction_pdf(&self, origin: Vec3f, dir: Vec3f) -> f64 {
+        let cos_max = match self.cone_cos_max(origin) {
+            Some(c) => c,
+            None => return 0.0,
+        };
+        let mut rec = HitRecord::default();
+        if !self.hit(Ray::new(origin, dir), 0.0001, f64::MAX, &mut rec) {
+            return 0.0;
+        }
+
+        1.0 / (2.0 * PI * (1.0 - cos_max))
+    }
+}
 
-            let temp = (-b - (b * b - a * c).sqrt()) / a;
-            if temp < t_max && temp > t_min {
-                rec.t = temp;
-                rec.p = ray.point_at(temp);
-                rec.normal = rec.p - self.center;
-                rec.normal.normalize();
+// Sphere moving in a straight line from `center0` at `time0` to `center1`
+// at `time1`, blurred by cameras with an open shutter. Stays at either end
+// outside of that range, so it never leaves its bounding box.
+#[derive(Clone)]
+pub struct MovingSphere {
+    center0: Vec3f,
+    center1: Vec3f,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    material: Material,
+}
 
-                return true;
-            }
-            let temp = (-b + (b * b - a * c).sqrt()) / a;
-            if temp < t_max && temp > t_min {
-                rec.t = temp;
-                rec.p = ray.point_at(temp);
-                rec.normal = rec.p - self.center;
-                rec.normal.normalize();
+impl MovingSphere {
+    pub fn new(
+        center0: Vec3f,
+        center1: Vec3f,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        record: HitRecord,
+    ) -> MovingSphere {
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material: record.material.clone(),
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Vec3f {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+
+        let f = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + (self.center1 - self.center0) * f
+    }
+}
 
-                return true;
-            }
+impl Visible for MovingSphere {
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        let center = self.center(ray.time());
+        if !hit_sphere(center, self.radius, ray, t_min, t_max, rec) {
+            return false;
         }
+        rec.material = &self.material;
 
-        false
+        true
     }
 
     fn set_material(&mut self, m: Material) {
-        self.record.material = m;
+        self.material = m;
+    }
+
+    // Covers the whole way between the two centers
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3f::new(self.radius, self.radius, self.radius);
+        let start = Aabb::new(self.center0 - r, self.center0 + r);
+        let end = Aabb::new(self.center1 - r, self.center1 + r);
+
+        Some(start.union(&end))
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::ray::Ray;
+    use crate::sphere::{sphere_uv, MovingSphere, Sphere};
+    use crate::vec::Vec3f;
+    use crate::visible::{HitRecord, Visible};
+
+    #[test]
+    fn uv_coordinates() {
+        assert_eq!(sphere_uv(Vec3f::new(-1.0, 0.0, 0.0)), (0.0, 0.5));
+        assert_eq!(sphere_uv(Vec3f::new(0.0, 0.0, 1.0)), (0.25, 0.5));
+        assert_eq!(sphere_uv(Vec3f::new(1.0, 0.0, 0.0)), (0.5, 0.5));
+        assert_eq!(sphere_uv(Vec3f::new(0.0, 0.0, -1.0)), (0.75, 0.5));
+        assert_eq!(sphere_uv(Vec3f::new(0.0, -1.0, 0.0)).1, 0.0);
+        assert_eq!(sphere_uv(Vec3f::new(0.0, 1.0, 0.0)).1, 1.0);
+    }
+
+    #[test]
+    fn hit_sets_uv() {
+        let sphere = Sphere::new(Vec3f::new(0.0, 0.0, -5.0), 2.0, HitRecord::default());
+        let mut rec = HitRecord::default();
+
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(sphere.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 3.0);
+        assert_eq!((rec.u, rec.v), (0.25, 0.5));
+    }
+
+    #[test]
+    fn moving_sphere() {
+        let sphere = MovingSphere::new(
+            Vec3f::new(0.0, 0.0, -5.0),
+            Vec3f::new(0.0, 2.0, -5.0),
+            0.0,
+            1.0,
+            0.5,
+            HitRecord::default(),
+        );
+        assert_eq!(sphere.center(0.5), Vec3f::new(0.0, 1.0, -5.0));
+        assert_eq!(sphere.center(-1.0), Vec3f::new(0.0, 0.0, -5.0));
+        assert_eq!(sphere.center(3.0), Vec3f::new(0.0, 2.0, -5.0));
+
+        let mut rec = HitRecord::default();
+        let at = |y, time| Ray::at_time(Vec3f::new(0.0, y, 0.0), Vec3f::new(0.0, 0.0, -1.0), time);
+        assert!(sphere.hit(at(0.0, 0.0), 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 4.5);
+        assert!(!sphere.hit(at(0.0, 1.0), 0.0001, f64::MAX, &mut rec));
+        assert!(sphere.hit(at(2.0, 1.0), 0.0001, f64::MAX, &mut rec));
+        assert!(sphere.hit(at(1.0, 0.5), 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.normal, Vec3f::new(0.0, 0.0, 1.0));
+
+        let b = sphere.bounding_box().unwrap();
+        assert_eq!(b.min(), Vec3f::new(-0.5, -0.5, -5.5));
+        assert_eq!(b.max(), Vec3f::new(0.5, 2.5, -4.5));
     }
 }
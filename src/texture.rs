@@ -0,0 +1,350 @@
+use crate::rng::Pcg32;
+use crate::tonemap::srgb_decode;
+use crate::vec::Vec3f;
+use rand::Rng;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// Color of a surface that can change from point to point. Looked up with the
+// surface coordinates of the hit and the hit point itself.
+#[derive(Clone)]
+pub enum Texture {
+    Solid(Vec3f),
+    // Cubes `scale` wide alternating between two textures. Uses the hit
+    // point, so it doesn't stretch around the poles of spheres.
+    Checker {
+        even: Arc<Texture>,
+        odd: Arc<Texture>,
+        scale: f64,
+    },
+    // Marble like veins of Perlin turbulence, `scale` sets how close they are
+    Noise {
+        perlin: Arc<Perlin>,
+        scale: f64,
+    },
+    Image(Arc<ImageTexture>),
+}
+
+impl Texture {
+    pub fn checker(even: Vec3f, odd: Vec3f, scale: f64) -> Texture {
+        Texture::Checker {
+            even: Arc::new(Texture::Solid(even)),
+            odd: Arc::new(Texture::Solid(odd)),
+            scale,
+        }
+    }
+
+    pub fn noise(scale: f64) -> Texture {
+        Texture::Noise {
+            perlin: Arc::new(Perlin::new(0)),
+            scale,
+        }
+    }
+
+    pub fn value(&self, u: f64, v: f64, p: Vec3f) -> Vec3f {
+        match self {
+            Texture::Solid(color) => *color,
+            Texture::Checker { even, odd, scale } => {
+                let cell =
+                    (p.x() / scale).floor() + (p.y() / scale).floor() + (p.z() / scale).floor();
+                if (cell as i64).rem_euclid(2) == 0 {
+                    even.value(u, v, p)
+                } else {
+                    odd.value(u, v, p)
+                }
+            }
+            Texture::Noise { perlin, scale } => {
+                let t = 0.5 * (1.0 + (scale * p.z() + 10.0 * perlin.turbulence(p, 7)).sin());
+                Vec3f::new(t, t, t)
+            }
+            Texture::Image(image) => image.value(u, v),
+        }
+    }
+}
+
+impl Default for Texture {
+    fn default() -> Texture {
+        Texture::Solid(Vec3f::new(0.8, 0.8, 0.8))
+    }
+}
+
+impl From<Vec3f> for Texture {
+    fn from(color: Vec3f) -> Texture {
+        Texture::Solid(color)
+    }
+}
+
+const POINT_COUNT: usize = 256;
+
+// Ken Perlin's gradient noise, smooth and repeating every 256 units
+pub struct Perlin {
+    gradients: Vec<Vec3f>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    // The same seed always gives the same noise
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = Pcg32::new(seed, 0);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| {
+                Vec3f::new(
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                )
+                .make_unit_vector()
+            })
+            .collect();
+        let perm_x = Perlin::permutation(&mut rng);
+        let perm_y = Perlin::permutation(&mut rng);
+        let perm_z = Perlin::permutation(&mut rng);
+
+        Perlin {
+            gradients,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    fn permutation(rng: &mut Pcg32) -> Vec<usize> {
+        let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+        for i in (1..POINT_COUNT).rev() {
+            let target = rng.gen_range(0, i + 1);
+            p.swap(i, target);
+        }
+
+        p
+    }
+
+    // In [-1, 1], zero at every integer point
+    pub fn noise(&self, p: Vec3f) -> f64 {
+        let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (u, v, w) = (p.x() - fx, p.y() - fy, p.z() - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+        let wrap = |n: i64| (n & (POINT_COUNT as i64 - 1)) as usize;
+
+        // Hermite smoothing hides the grid
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[wrap(i + di)]
+                        ^ self.perm_y[wrap(j + dj)]
+                        ^ self.perm_z[wrap(k + dk)];
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3f::new(u - a, v - b, w - c);
+
+                    sum += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * self.gradients[index].dot(&weight);
+                }
+            }
+        }
+
+        sum
+    }
+
+    // Sum of `depth` octaves of noise, each at twice the frequency and half
+    // the weight of the previous one
+    pub fn turbulence(&self, p: Vec3f, depth: usize) -> f64 {
+        let mut sum = 0.0;
+        let mut p = p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            sum += weight * self.noise(p);
+            weight *= 0.5;
+            p = p * 2.0;
+        }
+
+        sum.abs()
+    }
+}
+
+// Linear colors of a picture, row by row from the top
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3f>,
+}
+
+impl ImageTexture {
+    // None for an empty image or when there isn't one pixel per width *
+    // height
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3f>) -> Option<ImageTexture> {
+        if width == 0 || height == 0 || pixels.len() != width * height {
+            return None;
+        }
+
+        Some(ImageTexture {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    // Reads an 8 or 16 bit PNG, taking its colors as sRGB encoded
+    pub fn load(path: &Path) -> io::Result<ImageTexture> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut reader) = decoder
+            .read_info()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut data = vec![0; info.buffer_size()];
+        reader
+            .next_frame(&mut data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let channels = info.color_type.samples();
+        let decode = |b: u8| srgb_decode(f64::from(b) / 255.0);
+        let pixels = data
+            .chunks(channels)
+            .map(|px| {
+                if channels < 3 {
+                    Vec3f::new(decode(px[0]), decode(px[0]), decode(px[0]))
+                } else {
+                    Vec3f::new(decode(px[0]), decode(px[1]), decode(px[2]))
+                }
+            })
+            .collect();
+
+        ImageTexture::new(info.width as usize, info.height as usize, pixels)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "image has no pixels"))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Nearest pixel, with v = 0 at the bottom of the image. Coordinates out
+    // of [0, 1] are clamped to the edges.
+    pub fn value(&self, u: f64, v: f64) -> Vec3f {
+        if self.pixels.is_empty() {
+            return Vec3f::new(0.0, 1.0, 1.0);
+        }
+
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+
+        self.pixels[y * self.width + x]
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::texture::{ImageTexture, Perlin, Texture};
+    use crate::vec::Vec3f;
+    use std::env;
+    use std::fs::File;
+    use std::io::BufWriter;
+    use std::sync::Arc;
+
+    #[test]
+    fn solid_and_checker() {
+        let red = Vec3f::new(1.0, 0.0, 0.0);
+        assert_eq!(Texture::Solid(red).value(0.3, 0.7, Vec3f::default()), red);
+
+        let (white, black) = (Vec3f::new(1.0, 1.0, 1.0), Vec3f::default());
+        let checker = Texture::checker(white, black, 2.0);
+        let at = |x, y, z| checker.value(0.0, 0.0, Vec3f::new(x, y, z));
+        assert_eq!(at(0.5, 0.5, 0.5), white);
+        assert_eq!(at(2.5, 0.5, 0.5), black);
+        assert_eq!(at(2.5, 2.5, 0.5), white);
+        // No mirrored cells around zero
+        assert_eq!(at(-0.5, 0.5, 0.5), black);
+        assert_eq!(at(-2.5, 0.5, 0.5), white);
+    }
+
+    #[test]
+    fn perlin_noise() {
+        let perlin = Perlin::new(3);
+        let same = Perlin::new(3);
+
+        for i in 0..100 {
+            let p = Vec3f::new(i as f64 * 0.37, i as f64 * 0.11 - 5.0, i as f64 * 0.73);
+            let n = perlin.noise(p);
+            assert!((-1.0..=1.0).contains(&n));
+            assert_eq!(n, same.noise(p));
+        }
+        assert_eq!(perlin.noise(Vec3f::new(4.0, -2.0, 7.0)), 0.0);
+        assert_ne!(perlin.noise(Vec3f::new(0.5, 0.5, 0.5)), 0.0);
+
+        let marble = Texture::noise(4.0).value(0.0, 0.0, Vec3f::new(0.2, 0.4, 0.6));
+        assert!(marble.x() >= 0.0 && marble.x() <= 1.0);
+        assert_eq!(marble.x(), marble.y());
+    }
+
+    #[test]
+    fn image_lookup() {
+        // Top row red and green, bottom row blue and white
+        let image = ImageTexture::new(
+            2,
+            2,
+            vec![
+                Vec3f::new(1.0, 0.0, 0.0),
+                Vec3f::new(0.0, 1.0, 0.0),
+                Vec3f::new(0.0, 0.0, 1.0),
+                Vec3f::new(1.0, 1.0, 1.0),
+            ],
+        )
+        .unwrap();
+        let texture = Texture::Image(Arc::new(image));
+        let at = |u, v| texture.value(u, v, Vec3f::default());
+
+        assert_eq!(at(0.25, 0.75), Vec3f::new(1.0, 0.0, 0.0));
+        assert_eq!(at(0.75, 0.75), Vec3f::new(0.0, 1.0, 0.0));
+        assert_eq!(at(0.25, 0.25), Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!(at(1.0, 0.0), Vec3f::new(1.0, 1.0, 1.0));
+        assert_eq!(at(-3.0, 5.0), Vec3f::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn image_rejects_bad_size() {
+        assert!(ImageTexture::new(0, 0, Vec::new()).is_none());
+        assert!(ImageTexture::new(0, 2, Vec::new()).is_none());
+        assert!(ImageTexture::new(2, 2, vec![Vec3f::default(); 3]).is_none());
+    }
+
+    #[test]
+    fn load_png() {
+        let path = env::temp_dir().join(format!("shade-tree-texture-{}.png", std::process::id()));
+        {
+            let w = BufWriter::new(File::create(&path).unwrap());
+            let mut encoder = png::Encoder::new(w, 2, 1);
+            encoder.set_color(png::ColorType::RGBA);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer
+                .write_image_data(&[255, 0, 0, 255, 188, 188, 188, 255])
+                .unwrap();
+        }
+
+        let image = ImageTexture::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.value(0.0, 0.5), Vec3f::new(1.0, 0.0, 0.0));
+        // sRGB 188 is about half as bright once linear
+        assert!((image.value(1.0, 0.5).x() - 0.5).abs() < 0.01);
+
+        assert!(ImageTexture::load(&env::temp_dir().join("shade-tree-missing.png")).is_err());
+    }
+}
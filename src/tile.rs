@@ -0,0 +1,233 @@
+// Rectangle of the image rendered as one piece of work. Tiles at the right
+// and bottom edges are cut short when the image size isn't a multiple of the
+// tile size.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    pub fn pixel_count(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+// Order tiles get handed out in. Whatever the order, the image is the same.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum TileOrder {
+    // Left to right, top to bottom
+    Scanline,
+    // Outwards from the middle, where the interesting part usually is
+    #[default]
+    Spiral,
+    // Along a Hilbert curve, so consecutive tiles are always neighbours
+    Hilbert,
+}
+
+// Small enough to keep every thread busy until the end of a pass, big
+// enough that handing tiles out costs nothing
+pub const DEFAULT_TILE_SIZE: usize = 32;
+
+// Names accepted by TileOrder::from_name()
+pub const TILE_ORDER_NAMES: [&str; 3] = ["scanline", "spiral", "hilbert"];
+
+impl TileOrder {
+    pub fn from_name(name: &str) -> Option<TileOrder> {
+        match name {
+            "scanline" => Some(TileOrder::Scanline),
+            "spiral" => Some(TileOrder::Spiral),
+            "hilbert" => Some(TileOrder::Hilbert),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TileOrder::Scanline => "scanline",
+            TileOrder::Spiral => "spiral",
+            TileOrder::Hilbert => "hilbert",
+        }
+    }
+}
+
+// Splits a width x height image into tiles of `size` pixels a side, in
+// `order`
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+    let columns = width.div_ceil(size);
+    let rows = height.div_ceil(size);
+
+    let cells = match order {
+        TileOrder::Scanline => (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect(),
+        TileOrder::Spiral => spiral(columns, rows),
+        TileOrder::Hilbert => hilbert(columns, rows),
+    };
+
+    cells
+        .into_iter()
+        .map(|(column, row)| {
+            let (x, y) = (column * size, row * size);
+            Tile {
+                x,
+                y,
+                width: size.min(width - x),
+                height: size.min(height - y),
+            }
+        })
+        .collect()
+}
+
+// Walks a square spiral around the middle cell, one more step every other
+// turn, keeping the cells inside the grid until all of them are found
+fn spiral(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let total = columns * rows;
+    let mut cells = Vec::with_capacity(total);
+    let (mut x, mut y) = ((columns as i64 - 1) / 2, (rows as i64 - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+    let visit = |x: i64, y: i64, cells: &mut Vec<(usize, usize)>| {
+        if x >= 0 && y >= 0 && (x as usize) < columns && (y as usize) < rows {
+            cells.push((x as usize, y as usize));
+        }
+    };
+    visit(x, y, &mut cells);
+
+    let mut steps = 1;
+    let mut turn = 0;
+    while cells.len() < total {
+        let (dx, dy) = directions[turn % 4];
+        for _ in 0..steps {
+            x += dx;
+            y += dy;
+            visit(x, y, &mut cells);
+        }
+        turn += 1;
+        if turn % 2 == 0 {
+            steps += 1;
+        }
+    }
+
+    cells
+}
+
+// Hilbert curve over the smallest power of two square covering the grid,
+// skipping the cells outside it
+fn hilbert(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let n = columns.max(rows).next_power_of_two();
+
+    (0..n * n)
+        .map(|d| hilbert_cell(n, d))
+        .filter(|&(x, y)| x < columns && y < rows)
+        .collect()
+}
+
+// Cell at distance `d` along the curve filling an n x n square
+fn hilbert_cell(n: usize, d: usize) -> (usize, usize) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+    while s < n {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        // Rotate the quadrant so the curve joins up with its neighbours
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+
+    (x, y)
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::tile::{tiles, Tile, TileOrder, TILE_ORDER_NAMES};
+
+    // Every pixel belongs to exactly one tile
+    fn covers(width: usize, height: usize, tiles: &[Tile]) -> bool {
+        let mut count = vec![0; width * height];
+        for t in tiles.iter() {
+            for y in t.y..t.y + t.height {
+                for x in t.x..t.x + t.width {
+                    count[y * width + x] += 1;
+                }
+            }
+        }
+
+        count.iter().all(|c| *c == 1)
+    }
+
+    #[test]
+    fn every_order_covers_the_image() {
+        for name in TILE_ORDER_NAMES.iter() {
+            let order = TileOrder::from_name(name).unwrap();
+            assert_eq!(order.name(), *name);
+
+            for (width, height, size) in
+                [(100, 50, 16), (64, 64, 32), (7, 300, 8), (1, 1, 64)].iter()
+            {
+                let t = tiles(*width, *height, *size, order);
+                assert!(covers(*width, *height, &t), "{} {}x{}", name, width, height);
+            }
+        }
+        assert!(TileOrder::from_name("random").is_none());
+    }
+
+    #[test]
+    fn edge_tiles_are_cut_short() {
+        let t = tiles(100, 50, 32, TileOrder::Scanline);
+        assert_eq!(t.len(), 8);
+        assert_eq!(
+            t[0],
+            Tile {
+                x: 0,
+                y: 0,
+                width: 32,
+                height: 32
+            }
+        );
+        assert_eq!(
+            t[7],
+            Tile {
+                x: 96,
+                y: 32,
+                width: 4,
+                height: 18
+            }
+        );
+        assert_eq!(t[7].pixel_count(), 72);
+    }
+
+    #[test]
+    fn spiral_starts_in_the_middle() {
+        let t = tiles(160, 96, 32, TileOrder::Spiral);
+        assert_eq!((t[0].x, t[0].y), (64, 32));
+        // The next ring goes around it before anything further out
+        for tile in t[1..9].iter() {
+            assert!((tile.x as i64 - 64).abs() <= 32 && (tile.y as i64 - 32).abs() <= 32);
+        }
+    }
+
+    #[test]
+    fn hilbert_steps_to_neighbours() {
+        let t = tiles(256, 256, 32, TileOrder::Hilbert);
+        assert_eq!(t.len(), 64);
+        for pair in t.windows(2) {
+            let dx = (pair[0].x as i64 - pair[1].x as i64).abs();
+            let dy = (pair[0].y as i64 - pair[1].y as i64).abs();
+            assert_eq!(dx + dy, 32);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::vec::{Vec3f, Vec3i};
+
+// Names accepted by ToneMap::from_name()
+pub const TONE_MAP_NAMES: [&str; 3] = ["clamp", "reinhard", "aces"];
+
+// Curves squeezing unbounded radiance into the [0, 1] range of a display
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum ToneMap {
+    // Anything brighter than 1 turns white
+    #[default]
+    Clamp,
+    // x / (1 + x), which never quite reaches white
+    Reinhard,
+    // Krzysztof Narkowicz's fit of the ACES filmic curve, with a slight
+    // toe and a soft shoulder
+    Aces,
+}
+
+impl ToneMap {
+    pub fn from_name(name: &str) -> Option<ToneMap> {
+        match name {
+            "clamp" => Some(ToneMap::Clamp),
+            "reinhard" => Some(ToneMap::Reinhard),
+            "aces" => Some(ToneMap::Aces),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMap::Clamp => "clamp",
+            ToneMap::Reinhard => "reinhard",
+            ToneMap::Aces => "aces",
+        }
+    }
+
+    fn map(&self, x: f64) -> f64 {
+        let x = x.max(0.0);
+        let y = match self {
+            ToneMap::Clamp => x,
+            ToneMap::Reinhard => x / (1.0 + x),
+            ToneMap::Aces => {
+                // The fit expects radiance scaled down to the range of the
+                // original curve
+                let x = x * 0.6;
+                (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+            }
+        };
+
+        y.min(1.0)
+    }
+}
+
+// Linear to sRGB encoded, both in [0, 1]
+pub fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// sRGB encoded to linear, the inverse of srgb_encode()
+pub fn srgb_decode(x: f64) -> f64 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// How bright a linear color looks, with the Rec. 709 weights
+pub fn luminance(c: Vec3f) -> f64 {
+    0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b()
+}
+
+// How linear radiance turns into colors for a display or an 8 bit image
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct ToneMapping {
+    pub operator: ToneMap,
+    // In stops: +1 doubles the brightness before tone mapping
+    pub exposure: f64,
+}
+
+impl ToneMapping {
+    // Tone mapped color, still linear, in [0, 1]
+    pub fn apply(&self, c: Vec3f) -> Vec3f {
+        let scale = 2_f64.powf(self.exposure);
+
+        Vec3f::new(
+            self.operator.map(c.r() * scale),
+            self.operator.map(c.g() * scale),
+            self.operator.map(c.b() * scale),
+        )
+    }
+
+    // 0xRRGGBB pixel, sRGB encoded
+    pub fn to_hex(&self, c: Vec3f) -> u32 {
+        let c = self.apply(c);
+        let encoded = Vec3f::new(srgb_encode(c.r()), srgb_encode(c.g()), srgb_encode(c.b()));
+
+        Vec3i::new_from_f64(encoded).to_hex()
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::tonemap::{srgb_decode, srgb_encode, ToneMap, ToneMapping, TONE_MAP_NAMES};
+    use crate::vec::Vec3f;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn srgb_transfer() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!(close(srgb_encode(0.002), 0.02584));
+        assert!(close(srgb_encode(0.5), 0.73536));
+        assert!(close(srgb_encode(1.0), 1.0));
+
+        for x in [0.0, 0.002, 0.2, 0.5, 1.0].iter() {
+            assert!(close(srgb_decode(srgb_encode(*x)), *x));
+        }
+    }
+
+    #[test]
+    fn operators() {
+        for name in TONE_MAP_NAMES.iter() {
+            let op = ToneMap::from_name(name).unwrap();
+            assert_eq!(op.name(), *name);
+
+            // Black stays black, brighter stays brighter and nothing goes
+            // past white
+            assert_eq!(op.map(0.0), 0.0);
+            assert_eq!(op.map(-1.0), 0.0);
+            assert!(op.map(0.5) < op.map(0.9));
+            assert!(op.map(1000.0) <= 1.0);
+        }
+        assert!(ToneMap::from_name("filmic").is_none());
+
+        assert_eq!(ToneMap::Clamp.map(0.5), 0.5);
+        assert_eq!(ToneMap::Clamp.map(3.0), 1.0);
+        assert_eq!(ToneMap::Reinhard.map(1.0), 0.5);
+        assert!(ToneMap::Aces.map(100.0) > 0.99);
+    }
+
+    #[test]
+    fn exposure() {
+        let brighter = ToneMapping {
+            operator: ToneMap::Clamp,
+            exposure: 1.0,
+        };
+        assert_eq!(
+            brighter.apply(Vec3f::new(0.25, 0.5, 1.0)),
+            Vec3f::new(0.5, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn bright_pixels_dont_overflow() {
+        let tm = ToneMapping::default();
+
+        assert_eq!(tm.to_hex(Vec3f::new(0.0, 0.0, 0.0)), 0x00_0000);
+        assert_eq!(tm.to_hex(Vec3f::new(1.0, 1.0, 1.0)), 0xff_ffff);
+        // Used to spill into the red channel
+        assert_eq!(tm.to_hex(Vec3f::new(0.0, 5.0, 0.0)), 0x00_ff00);
+        assert_eq!(tm.to_hex(Vec3f::new(0.5, 0.5, 0.5)), 0xbc_bcbc);
+    }
+}
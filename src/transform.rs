@@ -0,0 +1,189 @@
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec::{Mat4, Vec3f};
+use crate::visible::{HitRecord, Visible};
+use std::sync::Arc;
+
+// An object moved, rotated or scaled by a matrix. Rays are taken into the
+// object's own space instead of changing the object, so one mesh can be
+// shared by any number of instances.
+pub struct Transform {
+    object: Arc<dyn Visible>,
+    matrix: Mat4,
+    inverse: Mat4,
+    // Inverse transpose, which keeps normals perpendicular to scaled
+    // surfaces
+    normal_matrix: Mat4,
+    // The object is shared, so a material for this instance alone lives here
+    material: Option<Material>,
+}
+
+impl Transform {
+    // None if the matrix can't be inverted, like a scaling by zero
+    pub fn new(object: Arc<dyn Visible>, matrix: Mat4) -> Option<Transform> {
+        let inverse = matrix.inverse()?;
+
+        Some(Transform {
+            object,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            material: None,
+        })
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        self.matrix
+    }
+}
+
+impl Visible for Transform {
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        let origin = self.inverse.transform_point(ray.origin());
+        let direction = self.inverse.transform_vector(ray.direction());
+        // Rays are kept unit length, so distances in object space are
+        // `scale` times longer
+        let scale = direction.length();
+        let local = Ray::at_time(origin, direction, ray.time());
+
+        if !self.object.hit(local, t_min * scale, t_max * scale, rec) {
+            return false;
+        }
+
+        rec.t /= scale;
+        rec.p = ray.point_at(rec.t);
+        rec.normal = self
+            .normal_matrix
+            .transform_vector(rec.normal)
+            .make_unit_vector();
+        if let Some(ref m) = self.material {
+            rec.material = m;
+        }
+
+        true
+    }
+
+    fn set_material(&mut self, material: Material) {
+        self.material = Some(material);
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let b = self.object.bounding_box()?;
+        let (lo, hi) = (b.min(), b.max());
+
+        let mut result = Aabb::empty();
+        for i in 0..8 {
+            let corner = Vec3f::new(
+                if i & 1 == 0 { lo.x() } else { hi.x() },
+                if i & 2 == 0 { lo.y() } else { hi.y() },
+                if i & 4 == 0 { lo.z() } else { hi.z() },
+            );
+            let p = self.matrix.transform_point(corner);
+            result = result.union(&Aabb::new(p, p));
+        }
+
+        Some(result)
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::material::Material;
+    use crate::quad::Quad;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::transform::Transform;
+    use crate::vec::{close, Mat4, Vec3f};
+    use crate::visible::{HitRecord, Visible};
+    use crate::world::World;
+    use std::sync::Arc;
+
+    #[test]
+    fn scaled_and_moved_sphere() {
+        let unit: Arc<dyn Visible> =
+            Arc::new(Sphere::new(Vec3f::default(), 1.0, HitRecord::default()));
+        let matrix = Mat4::translation(Vec3f::new(0.0, 0.0, -10.0))
+            * Mat4::scaling(Vec3f::new(2.0, 2.0, 2.0));
+        let t = Transform::new(unit, matrix).unwrap();
+
+        let mut rec = HitRecord::default();
+        let ray = Ray::new(Vec3f::default(), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(t.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!((rec.t - 8.0).abs() < 1e-9);
+        assert!(close(rec.p, Vec3f::new(0.0, 0.0, -8.0)));
+        assert!(close(rec.normal, Vec3f::new(0.0, 0.0, 1.0)));
+
+        // The range is in world distances
+        assert!(!t.hit(ray, 0.0001, 7.9, &mut rec));
+
+        let b = t.bounding_box().unwrap();
+        assert!(close(b.min(), Vec3f::new(-2.0, -2.0, -12.0)));
+        assert!(close(b.max(), Vec3f::new(2.0, 2.0, -8.0)));
+    }
+
+    #[test]
+    fn normals_of_stretched_objects() {
+        // A quad in the XY plane tilted 45 degrees around X, then squashed
+        // along Y. Its normal has to stay perpendicular to the surface.
+        let quad: Arc<dyn Visible> = Arc::new(Quad::new(
+            Vec3f::new(-1.0, -1.0, 0.0),
+            Vec3f::new(2.0, 0.0, 0.0),
+            Vec3f::new(0.0, 2.0, 0.0),
+        ));
+        let matrix = Mat4::scaling(Vec3f::new(1.0, 0.5, 1.0))
+            * Mat4::rotation(Vec3f::new(1.0, 0.0, 0.0), -45.0);
+        let t = Transform::new(quad, matrix).unwrap();
+
+        let mut rec = HitRecord::default();
+        let ray = Ray::new(Vec3f::new(0.1, 0.1, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(t.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        let along = matrix.transform_vector(Vec3f::new(0.0, 1.0, 0.0));
+        let across = matrix.transform_vector(Vec3f::new(1.0, 0.0, 0.0));
+        assert!(rec.normal.dot(&along).abs() < 1e-9);
+        assert!(rec.normal.dot(&across).abs() < 1e-9);
+        assert!((rec.normal.length() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn instances_share_the_object() {
+        let mut sphere = Sphere::new(Vec3f::default(), 0.5, HitRecord::default());
+        sphere.set_material(Material::Dielectric { ri: 1.5 });
+        let shared: Arc<dyn Visible> = Arc::new(sphere);
+
+        let mut world = World::default();
+        for i in 0..100 {
+            let offset = Vec3f::new(i as f64 * 2.0, 0.0, 0.0);
+            let mut instance = Transform::new(shared.clone(), Mat4::translation(offset)).unwrap();
+            if i == 42 {
+                instance.set_material(Material::default());
+            }
+            world.add(Box::new(instance));
+        }
+        world.build_acceleration();
+        assert_eq!(Arc::strong_count(&shared), 101);
+
+        let mut rec = HitRecord::default();
+        let ray = Ray::new(Vec3f::new(84.0, 0.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(world.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!(close(rec.p, Vec3f::new(84.0, 0.0, 0.5)));
+        assert!(matches!(rec.material, Material::Lambertian { .. }));
+
+        let ray = Ray::new(Vec3f::new(86.0, 0.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(world.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!(matches!(rec.material, Material::Dielectric { .. }));
+
+        let ray = Ray::new(Vec3f::new(85.0, 0.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!world.hit(ray, 0.0001, f64::MAX, &mut rec));
+    }
+
+    #[test]
+    fn flat_matrices_are_refused() {
+        let unit: Arc<dyn Visible> =
+            Arc::new(Sphere::new(Vec3f::default(), 1.0, HitRecord::default()));
+        let flat = Mat4::scaling(Vec3f::new(1.0, 0.0, 1.0));
+        assert!(Transform::new(unit, flat).is_none());
+    }
+}
@@ -0,0 +1,371 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec::Vec3f;
+use crate::visible::{HitRecord, Visible};
+
+// Determinants smaller than this mean the ray is parallel to the triangle
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+// Möller–Trumbore ray-triangle intersection. Returns the distance along the
+// ray and the barycentric coordinates of the hit relative to v1 and v2.
+fn intersect(v: &[Vec3f; 3], ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let edge1 = v[1] - v[0];
+    let edge2 = v[2] - v[0];
+    let pvec = ray.direction().cross(&edge2);
+    let det = edge1.dot(&pvec);
+    if det.abs() < PARALLEL_EPSILON {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let tvec = ray.origin() - v[0];
+    let b1 = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(&edge1);
+    let b2 = ray.direction().dot(&qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(&qvec) * inv_det;
+    if t < t_max && t > t_min {
+        Some((t, b1, b2))
+    } else {
+        None
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn fill_record<'a>(
+    rec: &mut HitRecord<'a>,
+    ray: &Ray,
+    (t, b1, b2): (f64, f64, f64),
+    v: &[Vec3f; 3],
+    normals: Option<[Vec3f; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    material: &'a Material,
+) {
+    let b0 = 1.0 - b1 - b2;
+
+    rec.t = t;
+    rec.p = ray.point_at(t);
+    rec.normal = match normals {
+        Some(n) => n[0] * b0 + n[1] * b1 + n[2] * b2,
+        None => (v[1] - v[0]).cross(&(v[2] - v[0])),
+    };
+    rec.normal.normalize();
+    let (u, v) = match uvs {
+        Some(uv) => (
+            uv[0].0 * b0 + uv[1].0 * b1 + uv[2].0 * b2,
+            uv[0].1 * b0 + uv[1].1 * b1 + uv[2].1 * b2,
+        ),
+        None => (b1, b2),
+    };
+    rec.u = u;
+    rec.v = v;
+    rec.material = material;
+}
+
+fn triangle_bounds(v: &[Vec3f; 3]) -> Aabb {
+    Aabb::new(v[0], v[0]).grow(v[1]).grow(v[2])
+}
+
+#[derive(Clone)]
+pub struct Triangle {
+    vertices: [Vec3f; 3],
+    normals: Option<[Vec3f; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    material: Material,
+}
+
+impl Triangle {
+    // Counter-clockwise vertices face the viewer
+    pub fn new(v0: Vec3f, v1: Vec3f, v2: Vec3f) -> Triangle {
+        Triangle {
+            vertices: [v0, v1, v2],
+            normals: None,
+            uvs: None,
+            material: Material::default(),
+        }
+    }
+
+    // Per-vertex normals, interpolated across the face for smooth shading
+    pub fn set_normals(&mut self, n0: Vec3f, n1: Vec3f, n2: Vec3f) {
+        self.normals = Some([n0, n1, n2]);
+    }
+
+    pub fn set_uvs(&mut self, uv0: (f64, f64), uv1: (f64, f64), uv2: (f64, f64)) {
+        self.uvs = Some([uv0, uv1, uv2]);
+    }
+}
+
+impl Visible for Triangle {
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        match intersect(&self.vertices, &ray, t_min, t_max) {
+            Some(hit) => {
+                fill_record(
+                    rec,
+                    &ray,
+                    hit,
+                    &self.vertices,
+                    self.normals,
+                    self.uvs,
+                    &self.material,
+                );
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(triangle_bounds(&self.vertices))
+    }
+}
+
+// Indexed triangle list sharing one material, with its own Bvh so a mesh is
+// a single object as far as the World is concerned
+pub struct TriangleMesh {
+    positions: Vec<Vec3f>,
+    // Either empty or one per position
+    normals: Vec<Vec3f>,
+    uvs: Vec<(f64, f64)>,
+    indices: Vec<[usize; 3]>,
+    material: Material,
+    bvh: Bvh,
+}
+
+impl TriangleMesh {
+    // None if the normals or uvs are neither empty nor one per position, or
+    // a face refers to a position that doesn't exist
+    pub fn new(
+        positions: Vec<Vec3f>,
+        normals: Vec<Vec3f>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+    ) -> Option<TriangleMesh> {
+        if !normals.is_empty() && normals.len() != positions.len() {
+            return None;
+        }
+        if !uvs.is_empty() && uvs.len() != positions.len() {
+            return None;
+        }
+        if indices.iter().flatten().any(|i| *i >= positions.len()) {
+            return None;
+        }
+
+        let boxes: Vec<Aabb> = indices
+            .iter()
+            .map(|i| triangle_bounds(&[positions[i[0]], positions[i[1]], positions[i[2]]]))
+            .collect();
+        let bvh = Bvh::new(&boxes);
+
+        Some(TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            indices,
+            material: Material::default(),
+            bvh,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn vertices(&self, face: usize) -> [Vec3f; 3] {
+        let i = self.indices[face];
+        [
+            self.positions[i[0]],
+            self.positions[i[1]],
+            self.positions[i[2]],
+        ]
+    }
+}
+
+impl Visible for TriangleMesh {
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        let mut closest_face = None;
+        self.bvh.hit(&ray, t_min, t_max, |face, closest| {
+            let hit = intersect(&self.vertices(face), &ray, t_min, closest)?;
+            closest_face = Some((face, hit));
+            Some(hit.0)
+        });
+
+        match closest_face {
+            Some((face, hit)) => {
+                let i = self.indices[face];
+                let normals = if self.normals.is_empty() {
+                    None
+                } else {
+                    Some([self.normals[i[0]], self.normals[i[1]], self.normals[i[2]]])
+                };
+                let uvs = if self.uvs.is_empty() {
+                    None
+                } else {
+                    Some([self.uvs[i[0]], self.uvs[i[1]], self.uvs[i[2]]])
+                };
+                fill_record(
+                    rec,
+                    &ray,
+                    hit,
+                    &self.vertices(face),
+                    normals,
+                    uvs,
+                    &self.material,
+                );
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_material(&mut self, m: Material) {
+        self.material = m;
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.is_empty() {
+            return None;
+        }
+
+        Some(
+            self.positions
+                .iter()
+                .fold(Aabb::empty(), |acc, p| acc.grow(*p)),
+        )
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::ray::Ray;
+    use crate::triangle::{Triangle, TriangleMesh};
+    use crate::vec::Vec3f;
+    use crate::visible::{HitRecord, Visible};
+
+    fn unit_triangle() -> Triangle {
+        Triangle::new(
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn triangle_hit() {
+        let tri = unit_triangle();
+        let ray = Ray::new(Vec3f::new(0.25, 0.25, 1.0), Vec3f::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+
+        assert!(tri.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 1.0);
+        assert_eq!(rec.p, Vec3f::new(0.25, 0.25, 0.0));
+        assert_eq!(rec.normal, Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!((rec.u, rec.v), (0.25, 0.25));
+    }
+
+    #[test]
+    fn triangle_miss() {
+        let tri = unit_triangle();
+        let mut rec = HitRecord::default();
+
+        // Outside the hypotenuse
+        let ray = Ray::new(Vec3f::new(0.75, 0.75, 1.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!tri.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Parallel to the plane of the triangle
+        let ray = Ray::new(Vec3f::new(-1.0, 0.25, 0.0), Vec3f::new(1.0, 0.0, 0.0));
+        assert!(!tri.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Pointing away
+        let ray = Ray::new(Vec3f::new(0.25, 0.25, 1.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(!tri.hit(ray, 0.0001, f64::MAX, &mut rec));
+
+        // Beyond t_max
+        let ray = Ray::new(Vec3f::new(0.25, 0.25, 1.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!tri.hit(ray, 0.0001, 0.5, &mut rec));
+    }
+
+    #[test]
+    fn triangle_interpolates_normals_and_uvs() {
+        let mut tri = unit_triangle();
+        tri.set_normals(
+            Vec3f::new(0.0, 0.0, 1.0),
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+        );
+        tri.set_uvs((0.0, 0.0), (1.0, 0.0), (1.0, 1.0));
+
+        let ray = Ray::new(Vec3f::new(0.5, 0.5, 1.0), Vec3f::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        assert!(tri.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.normal, Vec3f::new(1.0, 1.0, 0.0).make_unit_vector());
+        assert_eq!((rec.u, rec.v), (1.0, 0.5));
+    }
+
+    #[test]
+    fn mesh_finds_closest_face() {
+        // Two parallel quads, one behind the other
+        let positions = vec![
+            Vec3f::new(-1.0, -1.0, 0.0),
+            Vec3f::new(1.0, -1.0, 0.0),
+            Vec3f::new(1.0, 1.0, 0.0),
+            Vec3f::new(-1.0, 1.0, 0.0),
+            Vec3f::new(-1.0, -1.0, -2.0),
+            Vec3f::new(1.0, -1.0, -2.0),
+            Vec3f::new(1.0, 1.0, -2.0),
+            Vec3f::new(-1.0, 1.0, -2.0),
+        ];
+        let indices = vec![[4, 5, 6], [4, 6, 7], [0, 1, 2], [0, 2, 3]];
+        let mesh = TriangleMesh::new(positions, Vec::new(), Vec::new(), indices).unwrap();
+        assert_eq!(mesh.len(), 4);
+
+        let mut rec = HitRecord::default();
+        let ray = Ray::new(Vec3f::new(0.2, -0.3, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(mesh.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 5.0);
+
+        let ray = Ray::new(Vec3f::new(0.2, -0.3, -5.0), Vec3f::new(0.0, 0.0, 1.0));
+        assert!(mesh.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 3.0);
+
+        let ray = Ray::new(Vec3f::new(3.0, 0.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+        assert!(!mesh.hit(ray, 0.0001, f64::MAX, &mut rec));
+    }
+
+    #[test]
+    fn mesh_rejects_bad_input() {
+        let positions = vec![
+            Vec3f::new(0.0, 0.0, 0.0),
+            Vec3f::new(1.0, 0.0, 0.0),
+            Vec3f::new(0.0, 1.0, 0.0),
+        ];
+        let normals = vec![Vec3f::new(0.0, 0.0, 1.0)];
+        let uvs = vec![(0.0, 0.0), (1.0, 0.0)];
+
+        assert!(
+            TriangleMesh::new(positions.clone(), normals, Vec::new(), vec![[0, 1, 2]]).is_none()
+        );
+        assert!(TriangleMesh::new(positions.clone(), Vec::new(), uvs, vec![[0, 1, 2]]).is_none());
+        assert!(
+            TriangleMesh::new(positions.clone(), Vec::new(), Vec::new(), vec![[0, 1, 3]]).is_none()
+        );
+        assert!(TriangleMesh::new(positions, Vec::new(), Vec::new(), vec![[0, 1, 2]]).is_some());
+    }
+}
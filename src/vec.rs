@@ -7,7 +7,7 @@ pub struct Vec3f {
 }
 
 impl Vec3f {
-    pub fn new(x: f64, y: f64, z: f64) -> Vec3f {
+    pub const fn new(x: f64, y: f64, z: f64) -> Vec3f {
         Vec3f { e: [x, y, z] }
     }
 
@@ -47,6 +47,14 @@ impl Vec3f {
         self.e[2] = b;
     }
 
+    pub fn get(&self, axis: usize) -> f64 {
+        self.e[axis]
+    }
+
+    pub fn set(&mut self, axis: usize, value: f64) {
+        self.e[axis] = value;
+    }
+
     pub fn set_x(&mut self, x: f64) {
         self.e[0] = x;
     }
@@ -91,6 +99,20 @@ impl Vec3f {
             ],
         }
     }
+
+    // Two unit vectors that together with this one (assumed to be unit
+    // length) form an orthonormal basis
+    pub fn orthonormal_basis(&self) -> (Vec3f, Vec3f) {
+        let a = if self.x().abs() > 0.9 {
+            Vec3f::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3f::new(1.0, 0.0, 0.0)
+        };
+        let v = self.cross(&a).make_unit_vector();
+        let u = self.cross(&v);
+
+        (u, v)
+    }
 }
 
 impl Add for Vec3f {
@@ -177,6 +199,160 @@ impl fmt::Debug for Vec3f {
     }
 }
 
+// 4x4 matrix of an affine transform, row major. Points are columns on the
+// right, so a * b applies b first.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4 {
+    m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Mat4 {
+        Mat4 { m }
+    }
+
+    pub fn identity() -> Mat4 {
+        Mat4::scaling(Vec3f::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translation(offset: Vec3f) -> Mat4 {
+        let mut t = Mat4::identity();
+        for i in 0..3 {
+            t.m[i][3] = offset.get(i);
+        }
+
+        t
+    }
+
+    pub fn scaling(factors: Vec3f) -> Mat4 {
+        let mut s = Mat4 { m: [[0.0; 4]; 4] };
+        for i in 0..3 {
+            s.m[i][i] = factors.get(i);
+        }
+        s.m[3][3] = 1.0;
+
+        s
+    }
+
+    // Counter-clockwise when looking down `axis` towards the origin
+    pub fn rotation(axis: Vec3f, degrees: f64) -> Mat4 {
+        let a = axis.make_unit_vector();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let k = 1.0 - cos;
+        let (x, y, z) = (a.x(), a.y(), a.z());
+
+        Mat4::new([
+            [
+                cos + x * x * k,
+                x * y * k - z * sin,
+                x * z * k + y * sin,
+                0.0,
+            ],
+            [
+                y * x * k + z * sin,
+                cos + y * y * k,
+                y * z * k - x * sin,
+                0.0,
+            ],
+            [
+                z * x * k - y * sin,
+                z * y * k + x * sin,
+                cos + z * z * k,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> f64 {
+        self.m[row][column]
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut t = Mat4 { m: [[0.0; 4]; 4] };
+        for (i, row) in self.m.iter().enumerate() {
+            for (j, x) in row.iter().enumerate() {
+                t.m[j][i] = *x;
+            }
+        }
+
+        t
+    }
+
+    // Gauss-Jordan elimination with partial pivoting. None if the matrix
+    // squashes space flat and can't be undone, or has NaN or infinite
+    // entries.
+    pub fn inverse(&self) -> Option<Mat4> {
+        if self.m.iter().flatten().any(|x| !x.is_finite()) {
+            return None;
+        }
+
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = a[row][col];
+                for j in 0..4 {
+                    a[row][j] -= factor * a[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+
+        Some(Mat4 { m: inv })
+    }
+
+    pub fn transform_point(&self, p: Vec3f) -> Vec3f {
+        self.transform_vector(p) + Vec3f::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    // Directions ignore the translation
+    pub fn transform_vector(&self, v: Vec3f) -> Vec3f {
+        let row = |i: usize| self.m[i][0] * v.x() + self.m[i][1] * v.y() + self.m[i][2] * v.z();
+
+        Vec3f::new(row(0), row(1), row(2))
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Mat4 {
+        Mat4::identity()
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+
+        Mat4 { m }
+    }
+}
+
 pub struct Vec3i {
     e: [u32; 3],
 }
@@ -187,22 +363,13 @@ impl Vec3i {
         Vec3i { e: [x, y, z] }
     }
 
+    // Channels are clamped to [0, 1] so they can't spill into each other in
+    // to_hex()
     pub fn new_from_f64(o: Vec3f) -> Vec3i {
-        let r = if o.r() >= 0.0 {
-            (o.r() * 255.0) as u32
-        } else {
-            0
-        };
-        let g = if o.g() >= 0.0 {
-            (o.g() * 255.0) as u32
-        } else {
-            0
-        };
-        let b = if o.b() >= 0.0 {
-            (o.b() * 255.0) as u32
-        } else {
-            0
-        };
+        let to_u8 = |x: f64| (x.clamp(0.0, 1.0) * 255.0).round() as u32;
+        let r = to_u8(o.r());
+        let g = to_u8(o.g());
+        let b = to_u8(o.b());
 
         Vec3i { e: [r, g, b] }
     }
@@ -216,10 +383,16 @@ impl Vec3i {
     }
 }
 
+// Whether two vectors only differ by rounding errors, for tests all over
+#[cfg(test)]
+pub(crate) fn close(a: Vec3f, b: Vec3f) -> bool {
+    (a - b).length() < 1e-9
+}
+
 // Tests
 #[cfg(test)]
 mod tests {
-    use crate::vec::Vec3f;
+    use crate::vec::{close, Mat4, Vec3f};
 
     #[test]
     fn vec3f_default_and_new() {
@@ -269,6 +442,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn vec3f_orthonormal_basis() {
+        let w = Vec3f::new(1.0, 2.0, 3.0).make_unit_vector();
+        let (u, v) = w.orthonormal_basis();
+        assert!((u.length() - 1.0).abs() < 1e-12);
+        assert!((v.length() - 1.0).abs() < 1e-12);
+        assert!(u.dot(&v).abs() < 1e-12);
+        assert!(u.dot(&w).abs() < 1e-12);
+        assert!(v.dot(&w).abs() < 1e-12);
+    }
+
     #[test]
     fn vec3f_normalize() {
         let mut t = Vec3f::new(2.0, 3.0, 4.0);
@@ -278,4 +462,58 @@ mod tests {
             Vec3f::new(0.3713906763541037, 0.5570860145311556, 0.7427813527082074)
         );
     }
+
+    #[test]
+    fn mat4_transforms() {
+        let p = Vec3f::new(1.0, 2.0, 3.0);
+        assert_eq!(Mat4::identity().transform_point(p), p);
+
+        let t = Mat4::translation(Vec3f::new(1.0, -1.0, 0.5));
+        assert_eq!(t.transform_point(p), Vec3f::new(2.0, 1.0, 3.5));
+        assert_eq!(t.transform_vector(p), p);
+
+        let s = Mat4::scaling(Vec3f::new(2.0, 3.0, -1.0));
+        assert_eq!(s.transform_point(p), Vec3f::new(2.0, 6.0, -3.0));
+
+        let r = Mat4::rotation(Vec3f::new(0.0, 0.0, 1.0), 90.0);
+        assert!(close(
+            r.transform_vector(Vec3f::new(1.0, 0.0, 0.0)),
+            Vec3f::new(0.0, 1.0, 0.0)
+        ));
+        let r = Mat4::rotation(Vec3f::new(0.0, 1.0, 0.0), 90.0);
+        assert!(close(
+            r.transform_vector(Vec3f::new(0.0, 0.0, 1.0)),
+            Vec3f::new(1.0, 0.0, 0.0)
+        ));
+
+        // Scale first, then move
+        assert_eq!((t * s).transform_point(p), Vec3f::new(3.0, 5.0, -2.5));
+    }
+
+    #[test]
+    fn mat4_inverse() {
+        let m = Mat4::translation(Vec3f::new(3.0, -2.0, 1.0))
+            * Mat4::rotation(Vec3f::new(1.0, 1.0, 0.0), 30.0)
+            * Mat4::scaling(Vec3f::new(2.0, 0.5, 4.0));
+        let inv = m.inverse().unwrap();
+        let p = Vec3f::new(0.3, -7.0, 2.0);
+
+        assert!(close(inv.transform_point(m.transform_point(p)), p));
+        let id = m * inv;
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((id.get(i, j) - expected).abs() < 1e-12);
+            }
+        }
+        assert_eq!(m.transpose().transpose(), m);
+
+        assert!(Mat4::scaling(Vec3f::new(1.0, 0.0, 1.0)).inverse().is_none());
+        assert!(Mat4::scaling(Vec3f::new(1.0, f64::NAN, 1.0))
+            .inverse()
+            .is_none());
+        assert!(Mat4::translation(Vec3f::new(f64::INFINITY, 0.0, 0.0))
+            .inverse()
+            .is_none());
+    }
 }
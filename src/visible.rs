@@ -1,16 +1,50 @@
-use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::material::{Material, DEFAULT_MATERIAL};
 use crate::ray::Ray;
+use crate::rng::Pcg32;
 use crate::vec::Vec3f;
 
-#[derive(Default, Clone, Copy)]
-pub struct HitRecord {
+// Where a ray hit an object. The material is borrowed from the object, so
+// finding hits never copies materials around.
+#[derive(Copy, Clone)]
+pub struct HitRecord<'a> {
     pub t: f64,
     pub p: Vec3f,
     pub normal: Vec3f,
-    pub material: Material,
+    // Surface coordinates of the hit point
+    pub u: f64,
+    pub v: f64,
+    pub material: &'a Material,
+}
+
+impl Default for HitRecord<'_> {
+    fn default() -> Self {
+        HitRecord {
+            t: 0.0,
+            p: Vec3f::default(),
+            normal: Vec3f::default(),
+            u: 0.0,
+            v: 0.0,
+            material: &DEFAULT_MATERIAL,
+        }
+    }
 }
 
 pub trait Visible: Send + Sync {
-    fn hit(&self, r: Ray, t_min: f64, t_max: f64, surface: &mut HitRecord) -> bool;
+    fn hit<'a>(&'a self, r: Ray, t_min: f64, t_max: f64, surface: &mut HitRecord<'a>) -> bool;
     fn set_material(&mut self, material: Material);
+    // None for objects without finite bounds, those are never put in a Bvh
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // Direction from `origin` towards a random point of the object, used to
+    // sample lights. Objects that don't know how return None.
+    fn sample_direction(&self, _origin: Vec3f, _rng: &mut Pcg32) -> Option<Vec3f> {
+        None
+    }
+
+    // Probability density, over solid angle, of sample_direction() returning
+    // `dir`. Zero if the direction misses the object.
+    fn direction_pdf(&self, _origin: Vec3f, _dir: Vec3f) -> f64 {
+        0.0
+    }
 }
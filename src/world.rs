@@ -1,20 +1,98 @@
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::rng::Pcg32;
+use crate::sky::Sky;
+use crate::vec::Vec3f;
 use crate::visible::{HitRecord, Visible};
+use rand::Rng;
 
 #[derive(Default)]
 pub struct World {
     obj_list: Vec<Box<dyn Visible>>,
+    bvh: Option<Bvh>,
+    // Objects without a bounding box, always tested one by one
+    unbounded: Vec<usize>,
+    // Objects that get sampled directly when looking for light
+    lights: Vec<usize>,
+    sky: Sky,
 }
 
 impl World {
     pub fn add(&mut self, obj: Box<dyn Visible>) {
         self.obj_list.push(obj);
+        // The tree doesn't know about the new object anymore
+        self.bvh = None;
     }
-}
 
-impl Visible for World {
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+    // Adds an emissive object that integrators can aim at, instead of waiting
+    // for scattered rays to find it by chance
+    pub fn add_light(&mut self, obj: Box<dyn Visible>) {
+        self.lights.push(self.obj_list.len());
+        self.add(obj);
+    }
+
+    pub fn has_lights(&self) -> bool {
+        !self.lights.is_empty()
+    }
+
+    // Direction from `origin` towards a point on one of the lights, picked
+    // uniformly
+    pub fn sample_light(&self, origin: Vec3f, rng: &mut Pcg32) -> Option<Vec3f> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let i = rng.gen_range(0, self.lights.len());
+        self.obj_list[self.lights[i]].sample_direction(origin, rng)
+    }
+
+    // Density of sample_light() returning `dir`, over all the lights
+    pub fn light_pdf(&self, origin: Vec3f, dir: Vec3f) -> f64 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+
+        let sum: f64 = self
+            .lights
+            .iter()
+            .map(|&i| self.obj_list[i].direction_pdf(origin, dir))
+            .sum();
+        sum / self.lights.len() as f64
+    }
+
+    pub fn set_sky(&mut self, sky: Sky) {
+        self.sky = sky;
+    }
+
+    pub fn sky(&self) -> &Sky {
+        &self.sky
+    }
+
+    // Builds a Bvh over every object in the world. Needs to be called again
+    // after adding more objects, otherwise hit() falls back to a linear scan.
+    pub fn build_acceleration(&mut self) {
+        let mut bounded = Vec::new();
+        let mut boxes = Vec::new();
+        self.unbounded.clear();
+
+        for (i, obj) in self.obj_list.iter().enumerate() {
+            match obj.bounding_box() {
+                Some(b) => {
+                    bounded.push(i);
+                    boxes.push(b);
+                }
+                None => self.unbounded.push(i),
+            }
+        }
+
+        let bvh = Bvh::new(&boxes);
+        // Store object indices directly so traversal doesn't need the mapping
+        self.bvh = Some(bvh.remap(&bounded));
+    }
+
+    fn hit_linear<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
         let mut hit_anything = false;
         let mut closest = t_max;
         for obj in self.obj_list.iter() {
@@ -23,18 +101,196 @@ impl Visible for World {
             if obj.hit(ray, t_min, t_max, &mut tmp_rec) && tmp_rec.t < closest {
                 hit_anything = true;
                 closest = tmp_rec.t;
-                rec.t = tmp_rec.t;
-                rec.p = tmp_rec.p;
-                rec.normal = tmp_rec.normal;
-                rec.material = tmp_rec.material;
+                *rec = tmp_rec;
             }
         }
 
         hit_anything
     }
+}
+
+impl Visible for World {
+    fn hit<'a>(&'a self, ray: Ray, t_min: f64, t_max: f64, rec: &mut HitRecord<'a>) -> bool {
+        let bvh = match self.bvh {
+            Some(ref bvh) => bvh,
+            None => return self.hit_linear(ray, t_min, t_max, rec),
+        };
 
-    fn set_material(&mut self, _m: Material) {
-        println!("ERROR: Don't call set_material on the World.");
-        unreachable!();
+        let mut hit_anything = bvh.hit(&ray, t_min, t_max, |i, closest| {
+            let mut tmp_rec = HitRecord::default();
+            if self.obj_list[i].hit(ray, t_min, closest, &mut tmp_rec) {
+                let t = tmp_rec.t;
+                *rec = tmp_rec;
+                Some(t)
+            } else {
+                None
+            }
+        });
+
+        let mut closest = if hit_anything { rec.t } else { t_max };
+        for &i in self.unbounded.iter() {
+            let mut tmp_rec = HitRecord::default();
+            if self.obj_list[i].hit(ray, t_min, closest, &mut tmp_rec) {
+                hit_anything = true;
+                closest = tmp_rec.t;
+                *rec = tmp_rec;
+            }
+        }
+
+        hit_anything
+    }
+
+    // Gives every object the same material, like a group of them would
+    fn set_material(&mut self, m: Material) {
+        for obj in self.obj_list.iter_mut() {
+            obj.set_material(m.clone());
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.is_empty() || self.obj_list.is_empty() {
+            return None;
+        }
+
+        self.obj_list.iter().try_fold(Aabb::empty(), |acc, obj| {
+            Some(acc.union(&obj.bounding_box()?))
+        })
+    }
+}
+
+// Tests
+#[cfg(test)]
+mod tests {
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::texture::Texture;
+    use crate::vec::Vec3f;
+    use crate::visible::{HitRecord, Visible};
+    use crate::world::World;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_spheres(rng: &mut StdRng, count: usize) -> Vec<Sphere> {
+        (0..count)
+            .map(|_| {
+                let center = Vec3f::new(
+                    rng.gen_range(-20.0, 20.0),
+                    rng.gen_range(-20.0, 20.0),
+                    rng.gen_range(-20.0, 20.0),
+                );
+                let mut s = Sphere::new(center, rng.gen_range(0.05, 1.5), HitRecord::default());
+                s.set_material(Material::Metal {
+                    albedo: Texture::Solid(Vec3f::new(rng.gen_range(0.0, 1.0), 0.5, 0.5)),
+                    fuzz: 0.0,
+                });
+                s
+            })
+            .collect()
+    }
+
+    fn random_ray(rng: &mut StdRng) -> Ray {
+        let orig = Vec3f::new(
+            rng.gen_range(-30.0, 30.0),
+            rng.gen_range(-30.0, 30.0),
+            rng.gen_range(-30.0, 30.0),
+        );
+        // Aim roughly at the cluster so most rays hit something
+        let target = Vec3f::new(
+            rng.gen_range(-10.0, 10.0),
+            rng.gen_range(-10.0, 10.0),
+            rng.gen_range(-10.0, 10.0),
+        );
+        Ray::new(orig, target - orig)
+    }
+
+    #[test]
+    fn bvh_matches_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let spheres = random_spheres(&mut rng, 1000);
+
+        let mut linear = World::default();
+        let mut accel = World::default();
+        for s in spheres.iter() {
+            linear.add(Box::new(s.clone()));
+            accel.add(Box::new(s.clone()));
+        }
+        // The ground is big enough to overlap most of the other nodes
+        let ground = Sphere::new(Vec3f::new(0.0, -1030.0, 0.0), 1000.0, HitRecord::default());
+        linear.add(Box::new(ground.clone()));
+        accel.add(Box::new(ground));
+        accel.build_acceleration();
+
+        let mut hits = 0;
+        for _ in 0..5000 {
+            let ray = random_ray(&mut rng);
+            let mut a = HitRecord::default();
+            let mut b = HitRecord::default();
+            let hit_a = linear.hit(ray, 0.0001, f64::MAX, &mut a);
+            let hit_b = accel.hit(ray, 0.0001, f64::MAX, &mut b);
+
+            assert_eq!(hit_a, hit_b);
+            if hit_a {
+                hits += 1;
+                assert_eq!(a.t, b.t);
+                assert_eq!(a.p, b.p);
+                assert_eq!(a.normal, b.normal);
+            }
+        }
+        assert!(hits > 1000);
+    }
+
+    #[test]
+    fn bvh_respects_t_max() {
+        let mut world = World::default();
+        world.add(Box::new(Sphere::new(
+            Vec3f::new(0.0, 0.0, -10.0),
+            1.0,
+            HitRecord::default(),
+        )));
+        world.add(Box::new(Sphere::new(
+            Vec3f::new(0.0, 0.0, -5.0),
+            1.0,
+            HitRecord::default(),
+        )));
+        world.build_acceleration();
+
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        assert!(world.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert_eq!(rec.t, 4.0);
+        assert!(!world.hit(ray, 0.0001, 3.5, &mut rec));
+    }
+
+    #[test]
+    fn empty_world() {
+        let mut world = World::default();
+        world.build_acceleration();
+
+        let ray = Ray::new(Vec3f::new(0.0, 0.0, 0.0), Vec3f::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        assert!(!world.hit(ray, 0.0001, f64::MAX, &mut rec));
+        assert!(world.bounding_box().is_none());
+    }
+
+    #[test]
+    fn set_material_on_every_object() {
+        let mut world = World::default();
+        for x in [-2.0, 2.0].iter() {
+            world.add(Box::new(Sphere::new(
+                Vec3f::new(*x, 0.0, 0.0),
+                1.0,
+                HitRecord::default(),
+            )));
+        }
+        world.set_material(Material::Dielectric { ri: 1.5 });
+        world.build_acceleration();
+
+        let mut rec = HitRecord::default();
+        for x in [-2.0, 2.0].iter() {
+            let ray = Ray::new(Vec3f::new(*x, 0.0, 5.0), Vec3f::new(0.0, 0.0, -1.0));
+            assert!(world.hit(ray, 0.0001, f64::MAX, &mut rec));
+            assert!(matches!(rec.material, Material::Dielectric { .. }));
+        }
     }
 }
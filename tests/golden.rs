@@ -0,0 +1,169 @@
+// Renders the scenes in tests/scenes and compares them with the reference
+// images in tests/golden.
+//
+// Renders are deterministic, so any difference means the output changed.
+// A little is tolerated so floating point differences between platforms
+// don't break the build. When a check fails the rendered image and a diff
+// are written to target/golden/ to look at.
+//
+// After an intended change, regenerate the references with:
+//
+//   UPDATE_GOLDEN=1 cargo test --test golden
+
+extern crate png;
+extern crate shade_tree;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+// Root mean square error over every channel, in [0, 1]
+const MAX_RMSE: f64 = 0.01;
+// Differences are hard to see at their real size
+const DIFF_SCALE: f64 = 8.0;
+
+struct Image {
+    width: usize,
+    height: usize,
+    // RGB, 8 bits per channel
+    data: Vec<u8>,
+}
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn read_png(path: &Path) -> Image {
+    let file =
+        File::open(path).unwrap_or_else(|e| panic!("Could not open {}: {}", path.display(), e));
+    let (info, mut reader) = png::Decoder::new(file).read_info().unwrap();
+    assert_eq!(info.color_type, png::ColorType::RGB);
+
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data).unwrap();
+
+    Image {
+        width: info.width as usize,
+        height: info.height as usize,
+        data,
+    }
+}
+
+fn write_png(path: &Path, image: &Image) {
+    let file = File::create(path).unwrap();
+    let w = &mut BufWriter::new(file);
+
+    let mut encoder = png::Encoder::new(w, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&image.data).unwrap();
+}
+
+fn rmse(a: &Image, b: &Image) -> f64 {
+    let sum: f64 = a
+        .data
+        .iter()
+        .zip(b.data.iter())
+        .map(|(x, y)| {
+            let d = (f64::from(*x) - f64::from(*y)) / 255.0;
+            d * d
+        })
+        .sum();
+
+    (sum / a.data.len() as f64).sqrt()
+}
+
+fn diff(a: &Image, b: &Image) -> Image {
+    let data = a
+        .data
+        .iter()
+        .zip(b.data.iter())
+        .map(|(x, y)| {
+            let d = (f64::from(*x) - f64::from(*y)).abs() * DIFF_SCALE;
+            d.min(255.0) as u8
+        })
+        .collect();
+
+    Image {
+        width: a.width,
+        height: a.height,
+        data,
+    }
+}
+
+fn render(scene_name: &str) -> Image {
+    let path = manifest_dir()
+        .join("tests/scenes")
+        .join(format!("{}.toml", scene_name));
+    let mut scene = shade_tree::load_scene(&path)
+        .unwrap_or_else(|e| panic!("Error loading scene {}: {}", path.display(), e));
+    scene.settings.progress = false;
+    let (width, height) = (scene.settings.width, scene.settings.height);
+
+    Image {
+        width,
+        height,
+        data: shade_tree::vec_from_hex(shade_tree::render_scene(scene).unwrap()),
+    }
+}
+
+fn check_golden(scene_name: &str) {
+    let actual = render(scene_name);
+    let golden_path = manifest_dir()
+        .join("tests/golden")
+        .join(format!("{}.png", scene_name));
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        write_png(&golden_path, &actual);
+        return;
+    }
+
+    let golden = read_png(&golden_path);
+    assert_eq!(
+        (actual.width, actual.height),
+        (golden.width, golden.height),
+        "{}: size differs from the reference",
+        scene_name
+    );
+
+    let error = rmse(&actual, &golden);
+    if error > MAX_RMSE {
+        let out_dir = manifest_dir().join("target/golden");
+        fs::create_dir_all(&out_dir).unwrap();
+        let actual_path = out_dir.join(format!("{}.png", scene_name));
+        let diff_path = out_dir.join(format!("{}.diff.png", scene_name));
+        write_png(&actual_path, &actual);
+        write_png(&diff_path, &diff(&actual, &golden));
+
+        panic!(
+            "{}: RMSE {:.4} is over {}. Rendered {}, diff {}",
+            scene_name,
+            error,
+            MAX_RMSE,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[test]
+fn golden_spheres() {
+    check_golden("spheres");
+}
+
+#[test]
+fn golden_cornell() {
+    check_golden("cornell");
+}
+
+#[test]
+fn golden_mesh() {
+    check_golden("mesh");
+}
+
+#[test]
+fn golden_textures() {
+    check_golden("textures");
+}